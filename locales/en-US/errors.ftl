@@ -0,0 +1,83 @@
+invalid-fields = One or more fields are invalid.
+unsupported-file-type = Only images and videos are allowed. One of the files has the following type: { $content_type }.
+too-many-files = Upload at most { $max } files at a time.
+missing-content-type = The "Content-Type" header is missing.
+missing-content-disposition = The "Content-Disposition" header is missing.
+file-name-not-extracted = Failed to extract the name of the file "{ $file }".
+file-name-not-converted = Failed to convert the name of the file "{ $file }".
+file-extension-not-extracted = Failed to extract the extension of the file "{ $file }".
+file-extension-not-converted = Failed to convert the extension of the file "{ $file }".
+file-directory-not-created = Could not create the directory for the file "{ $file }".
+file-not-saved = Could not save the file "{ $file }".
+database-unavailable = Could not connect to the database.
+attachment-not-found = Attachment "{ $uuid }" not found.
+attachment-not-opened = Could not open the file of the attachment "{ $uuid }".
+empty-collection-name = The collection name can't be empty.
+collection-not-found = Collection "{ $uuid }" not found.
+publish-at-in-past = The publication date { $publish_at } has already passed.
+referenced-post-not-found = The post you are trying to reply to or quote doesn't exist.
+empty-post = A post can't be empty without attachments.
+draft-not-found = Draft "{ $uuid }" not found.
+draft-parent-not-found = Could not publish the draft "{ $uuid }" because the post it replies to no longer exists.
+draft-quote-not-found = Could not publish the draft "{ $uuid }" because the post it quotes no longer exists.
+post-not-found = Post "{ $uuid }" not found.
+invalid-filter-phrase-length = The filter term must have between 1 and { $max } characters.
+filter-phrase-with-spaces = Filtered words and hashtags can't contain spaces.
+invalid-filter-duration = The filter duration must be positive.
+filter-not-found = Filter "{ $uuid }" not found.
+payload-too-large = The request body is too large.
+invalid-json = Invalid request body: { $error }.
+invalid-query = Invalid parameters: { $error }.
+invalid-multipart = Invalid upload: { $error }.
+invalid-poll-option-count = A poll must have between { $min } and { $max } options.
+invalid-poll-option-length = Poll options must have between 1 and { $max } characters.
+invalid-poll-duration = A poll must last between { $min_minutes } minutes and { $max_days } days.
+poll-not-found = The post "{ $uuid }" has no poll.
+poll-closed = The poll of the post "{ $uuid }" is already closed.
+poll-choice-missing = Choose at least one poll option.
+too-many-poll-choices = Choose only one poll option.
+already-voted = You already voted on the poll of the post "{ $uuid }".
+invalid-poll-choice = One of the choices is not an option of the poll of the post "{ $uuid }".
+poll-not-loaded = Could not load the poll of the post "{ $uuid }".
+post-too-long = A post must have at most { $max } characters, but it has { $length }.
+content-warning-too-long = The content warning must have at most { $max } characters.
+quoted-post-not-found = Could not create the post. Maybe the post { $uuid } (which you are trying to quote) doesn't exist or the given text is invalid.
+parent-post-not-found = Could not create the post. Maybe the post { $uuid } (which you are trying to reply to) doesn't exist or the given text is invalid.
+attachments-unavailable = One of the attachments doesn't exist or is already in use.
+already-reacted = You already reacted with { $reaction } to the post { $uuid }.
+reaction-not-found = You haven't reacted with { $reaction } to the post { $uuid }.
+reaction-unavailable = The reaction { $reaction } is not available on this instance.
+already-reposted = You already reposted the post { $uuid }.
+repost-not-found = You haven't reposted the post { $uuid }.
+pin-limit-reached = You already pinned the maximum of { $max } posts.
+already-pinned = The post { $uuid } is already pinned.
+pin-not-found = The post { $uuid } isn't pinned.
+bookmark-not-found = The post { $uuid } isn't saved.
+user-not-found = User "{ $username }" not found.
+likes-private = The likes of { $username } are private.
+already-following = You already follow { $username }.
+follow-not-found = You don't follow { $username }.
+already-blocked = You already blocked { $username }.
+block-not-found = { $username } isn't blocked.
+already-muted = You already muted { $username }.
+mute-not-found = { $username } isn't muted.
+app-state-unavailable = Could not get the application data.
+authorization-header-not-converted = Failed to convert the authorization header.
+authorization-token-missing = Failed to get the authorization token from the header value.
+missing-access-token = Access denied because the "accessToken" cookie was not found.
+invalid-access-token = Could not decode the token.
+unknown-user = User "{ $username }" doesn't exist.
+registrations-closed = This instance is not accepting new registrations.
+password-not-hashed = Failed to hash the password.
+token-not-generated = Failed to generate the token.
+password-not-verified = Failed to verify the password.
+invalid-credentials = Invalid credentials.
+token-not-renewed = Failed to generate a new token.
+unsupported-locale = Unsupported locale: { $locale }. The available locales are { $supported }.
+resource-not-found = The requested resource was not found.
+resource-conflict = The resource already exists.
+constraint-violation = The request violates a data constraint.
+internal-error = An internal error occurred.
+user-already-exists = A user with this username or email already exists.
+invalid-username = A username must have from 1 to { $max } letters, numbers or underscores.
+invalid-email = The email address is not valid.
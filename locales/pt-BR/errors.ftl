@@ -0,0 +1,83 @@
+invalid-fields = Um ou mais campos são inválidos.
+unsupported-file-type = Somente imagens e vídeos são permitidos. Um dos arquivos tem o seguinte tipo: { $content_type }.
+too-many-files = Envie no máximo { $max } arquivos por vez.
+missing-content-type = O cabeçalho "Content-Type" não está presente.
+missing-content-disposition = O cabeçalho "Content-Disposition" não está presente.
+file-name-not-extracted = Falha ao extrair o nome do arquivo "{ $file }".
+file-name-not-converted = Falha ao converter o nome do arquivo "{ $file }".
+file-extension-not-extracted = Falha ao extrair a extensão do arquivo "{ $file }".
+file-extension-not-converted = Falha ao converter a extensão do arquivo "{ $file }".
+file-directory-not-created = Não foi possível criar o diretório para o arquivo "{ $file }".
+file-not-saved = Não foi possível salvar o arquivo "{ $file }".
+database-unavailable = Impossível conectar ao banco de dados.
+attachment-not-found = Anexo "{ $uuid }" não encontrado.
+attachment-not-opened = Não foi possível abrir o arquivo do anexo "{ $uuid }".
+empty-collection-name = O nome da coleção não pode ser vazio.
+collection-not-found = Coleção "{ $uuid }" não encontrada.
+publish-at-in-past = A data de publicação { $publish_at } já passou.
+referenced-post-not-found = A postagem que você está tentando responder ou citar não existe.
+empty-post = A postagem não pode ser vazia sem anexos.
+draft-not-found = Rascunho "{ $uuid }" não encontrado.
+draft-parent-not-found = Não foi possível publicar o rascunho "{ $uuid }" porque a postagem que ele responde não existe mais.
+draft-quote-not-found = Não foi possível publicar o rascunho "{ $uuid }" porque a postagem que ele cita não existe mais.
+post-not-found = Postagem "{ $uuid }" não encontrada.
+invalid-filter-phrase-length = O termo do filtro deve ter entre 1 e { $max } caracteres.
+filter-phrase-with-spaces = Palavras e hashtags filtradas não podem conter espaços.
+invalid-filter-duration = A duração do filtro deve ser positiva.
+filter-not-found = Filtro "{ $uuid }" não encontrado.
+payload-too-large = O corpo da requisição é grande demais.
+invalid-json = Corpo da requisição inválido: { $error }.
+invalid-query = Parâmetros inválidos: { $error }.
+invalid-multipart = Envio inválido: { $error }.
+invalid-poll-option-count = Uma enquete deve ter entre { $min } e { $max } opções.
+invalid-poll-option-length = As opções da enquete devem ter entre 1 e { $max } caracteres.
+invalid-poll-duration = Uma enquete deve durar entre { $min_minutes } minutos e { $max_days } dias.
+poll-not-found = A postagem "{ $uuid }" não tem uma enquete.
+poll-closed = A enquete da postagem "{ $uuid }" já foi encerrada.
+poll-choice-missing = Escolha ao menos uma opção da enquete.
+too-many-poll-choices = Escolha somente uma opção da enquete.
+already-voted = Você já votou na enquete da postagem "{ $uuid }".
+invalid-poll-choice = Uma das escolhas não é uma opção da enquete da postagem "{ $uuid }".
+poll-not-loaded = Não foi possível carregar a enquete da postagem "{ $uuid }".
+post-too-long = A postagem deve ter no máximo { $max } caracteres, mas tem { $length }.
+content-warning-too-long = O aviso de conteúdo deve ter no máximo { $max } caracteres.
+quoted-post-not-found = Não foi possível criar a postagem. Talvez a postagem { $uuid } (a qual você está tentando citar) não exista ou o corpo de texto fornecido seja inválido.
+parent-post-not-found = Não foi possível criar a postagem. Talvez a postagem { $uuid } (a qual você está tentando responder) não exista ou o corpo de texto fornecido seja inválido.
+attachments-unavailable = Algum dos anexos não existe ou já está em uso.
+already-reacted = Você já reagiu com { $reaction } à postagem { $uuid }.
+reaction-not-found = Você não reagiu com { $reaction } à postagem { $uuid }.
+reaction-unavailable = A reação { $reaction } não está disponível nesta instância.
+already-reposted = Você já compartilhou a postagem { $uuid }.
+repost-not-found = Você não compartilhou a postagem { $uuid }.
+pin-limit-reached = Você já fixou o máximo de { $max } postagens.
+already-pinned = A postagem { $uuid } já está fixada.
+pin-not-found = A postagem { $uuid } não está fixada.
+bookmark-not-found = A postagem { $uuid } não está salva.
+user-not-found = Usuário "{ $username }" não encontrado.
+likes-private = As curtidas de { $username } são privadas.
+already-following = Você já segue { $username }.
+follow-not-found = Você não segue { $username }.
+already-blocked = Você já bloqueou { $username }.
+block-not-found = { $username } não está bloqueado.
+already-muted = Você já silenciou { $username }.
+mute-not-found = { $username } não está silenciado.
+app-state-unavailable = Impossível obter dados da aplicação.
+authorization-header-not-converted = Falha ao converter o cabeçalho de autorização.
+authorization-token-missing = Falha ao obter a chave de autorização do valor do cabeçalho.
+missing-access-token = Acesso negado porque o cookie "accessToken" não foi encontrado.
+invalid-access-token = Não foi possível decodificar a chave.
+unknown-user = Usuário "{ $username }" inexistente.
+registrations-closed = Esta instância não está aceitando novos cadastros.
+password-not-hashed = Falha ao criptografar a senha.
+token-not-generated = Falha ao gerar a chave.
+password-not-verified = Falha ao descriptografar a senha.
+invalid-credentials = Credenciais inválidas.
+token-not-renewed = Falha ao gerar uma nova chave.
+unsupported-locale = Idioma não suportado: { $locale }. Os idiomas disponíveis são { $supported }.
+resource-not-found = O recurso solicitado não foi encontrado.
+resource-conflict = O recurso já existe.
+constraint-violation = A requisição viola uma restrição dos dados.
+internal-error = Ocorreu um erro interno.
+user-already-exists = Já existe um usuário com esse nome de usuário ou e-mail.
+invalid-username = O nome de usuário deve ter de 1 a { $max } letras, números ou sublinhados.
+invalid-email = O endereço de e-mail não é válido.
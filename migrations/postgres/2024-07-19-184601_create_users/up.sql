@@ -0,0 +1,10 @@
+CREATE TABLE users (
+  id SERIAL PRIMARY KEY,
+  username VARCHAR(64) NOT NULL UNIQUE,
+  email VARCHAR(256) NOT NULL UNIQUE,
+  real_name VARCHAR(256) NOT NULL,
+  summary VARCHAR(1024) NOT NULL,
+  password VARCHAR(256) NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE
+);
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE attachments (
+    id SERIAL PRIMARY KEY,
+    uploader_id INTEGER NOT NULL,
+    uuid VARCHAR(8) NOT NULL UNIQUE,
+    file_name VARCHAR(256) NOT NULL,
+    uploaded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY(uploader_id) REFERENCES users(id)
+);
\ No newline at end of file
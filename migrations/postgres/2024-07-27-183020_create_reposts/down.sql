@@ -0,0 +1,4 @@
+ALTER TABLE posts DROP COLUMN quote_count;
+ALTER TABLE posts DROP COLUMN repost_count;
+ALTER TABLE posts DROP COLUMN quote_of_id;
+ALTER TABLE posts DROP COLUMN repost_of_id;
\ No newline at end of file
@@ -0,0 +1,4 @@
+ALTER TABLE posts ADD COLUMN repost_of_id INTEGER;
+ALTER TABLE posts ADD COLUMN quote_of_id INTEGER;
+ALTER TABLE posts ADD COLUMN repost_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE posts ADD COLUMN quote_count INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1,21 @@
+CREATE TABLE bookmark_collections (
+  id SERIAL PRIMARY KEY,
+  uuid VARCHAR(8) NOT NULL UNIQUE,
+  owner_id INTEGER NOT NULL,
+  name VARCHAR(64) NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(owner_id) REFERENCES users(id)
+);
+
+CREATE TABLE bookmarks (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  post_id INTEGER NOT NULL,
+  collection_id INTEGER,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(user_id) REFERENCES users(id),
+  FOREIGN KEY(post_id) REFERENCES posts(id),
+  FOREIGN KEY(collection_id) REFERENCES bookmark_collections(id)
+);
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN likes_public BOOLEAN NOT NULL DEFAULT TRUE;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE likes ADD COLUMN emoji VARCHAR(32) NOT NULL DEFAULT '❤️';
\ No newline at end of file
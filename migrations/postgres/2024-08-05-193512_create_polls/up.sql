@@ -0,0 +1,28 @@
+CREATE TABLE polls (
+  id SERIAL PRIMARY KEY,
+  post_id INTEGER NOT NULL UNIQUE,
+  multiple_choice BOOLEAN NOT NULL DEFAULT FALSE,
+  closes_at TIMESTAMP NOT NULL,
+  FOREIGN KEY(post_id) REFERENCES posts(id)
+);
+
+CREATE TABLE poll_options (
+  id SERIAL PRIMARY KEY,
+  poll_id INTEGER NOT NULL,
+  position INTEGER NOT NULL,
+  label VARCHAR(64) NOT NULL,
+  vote_count INTEGER NOT NULL DEFAULT 0,
+  FOREIGN KEY(poll_id) REFERENCES polls(id)
+);
+
+CREATE TABLE poll_votes (
+  id SERIAL PRIMARY KEY,
+  poll_id INTEGER NOT NULL,
+  option_id INTEGER NOT NULL,
+  user_id INTEGER NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  UNIQUE(poll_id, option_id, user_id),
+  FOREIGN KEY(poll_id) REFERENCES polls(id),
+  FOREIGN KEY(option_id) REFERENCES poll_options(id),
+  FOREIGN KEY(user_id) REFERENCES users(id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN published BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE posts ADD COLUMN publish_at TIMESTAMP;
\ No newline at end of file
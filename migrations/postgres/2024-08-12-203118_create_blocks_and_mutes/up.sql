@@ -0,0 +1,19 @@
+CREATE TABLE blocks (
+  id SERIAL PRIMARY KEY,
+  blocker_id INTEGER NOT NULL,
+  blocked_id INTEGER NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(blocker_id) REFERENCES users(id),
+  FOREIGN KEY(blocked_id) REFERENCES users(id)
+);
+
+CREATE TABLE mutes (
+  id SERIAL PRIMARY KEY,
+  muter_id INTEGER NOT NULL,
+  muted_id INTEGER NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(muter_id) REFERENCES users(id),
+  FOREIGN KEY(muted_id) REFERENCES users(id)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE link_previews (
+  id SERIAL PRIMARY KEY,
+  url TEXT NOT NULL UNIQUE,
+  title VARCHAR(256) NOT NULL,
+  description VARCHAR(1024),
+  image_url TEXT,
+  fetched_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Reposting used to check for an existing repost before inserting, which let
+-- concurrent requests through. Duplicates are soft-deleted rather than removed,
+-- as other rows may refer to them; `microblogs-admin recount` fixes the counts.
+UPDATE posts SET deleted = TRUE
+  WHERE deleted = FALSE AND repost_of_id IS NOT NULL AND id NOT IN (
+    SELECT MIN(id) FROM posts
+    WHERE deleted = FALSE AND repost_of_id IS NOT NULL
+    GROUP BY poster_id, repost_of_id
+  );
+
+CREATE UNIQUE INDEX posts_poster_id_repost_of_id ON posts(poster_id, repost_of_id)
+  WHERE deleted = FALSE AND repost_of_id IS NOT NULL;
@@ -0,0 +1 @@
+DROP INDEX bookmarks_user_id_post_id;
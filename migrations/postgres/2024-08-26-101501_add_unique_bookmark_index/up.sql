@@ -0,0 +1,9 @@
+-- Bookmarking used to check for an existing bookmark before inserting, which
+-- let concurrent requests through, so keep the oldest of any duplicates.
+DELETE FROM bookmarks
+  WHERE deleted = FALSE AND id NOT IN (
+    SELECT MIN(id) FROM bookmarks WHERE deleted = FALSE GROUP BY user_id, post_id
+  );
+
+CREATE UNIQUE INDEX bookmarks_user_id_post_id ON bookmarks(user_id, post_id)
+  WHERE deleted = FALSE;
@@ -0,0 +1 @@
+DROP INDEX follows_follower_id_followee_id;
@@ -0,0 +1,2 @@
+DROP INDEX mutes_muter_id_muted_id;
+DROP INDEX blocks_blocker_id_blocked_id;
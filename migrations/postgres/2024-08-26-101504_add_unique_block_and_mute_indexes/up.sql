@@ -0,0 +1,17 @@
+-- Blocking and muting used to check for an existing row before inserting,
+-- which let concurrent requests through, so keep the oldest of any duplicates.
+DELETE FROM blocks
+  WHERE deleted = FALSE AND id NOT IN (
+    SELECT MIN(id) FROM blocks WHERE deleted = FALSE GROUP BY blocker_id, blocked_id
+  );
+
+CREATE UNIQUE INDEX blocks_blocker_id_blocked_id ON blocks(blocker_id, blocked_id)
+  WHERE deleted = FALSE;
+
+DELETE FROM mutes
+  WHERE deleted = FALSE AND id NOT IN (
+    SELECT MIN(id) FROM mutes WHERE deleted = FALSE GROUP BY muter_id, muted_id
+  );
+
+CREATE UNIQUE INDEX mutes_muter_id_muted_id ON mutes(muter_id, muted_id)
+  WHERE deleted = FALSE;
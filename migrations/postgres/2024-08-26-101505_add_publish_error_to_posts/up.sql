@@ -0,0 +1 @@
+ALTER TABLE posts ADD COLUMN publish_error VARCHAR(64);
@@ -0,0 +1,2 @@
+ALTER TABLE posts DROP COLUMN like_count;
+DROP TABLE IF EXISTS likes;
\ No newline at end of file
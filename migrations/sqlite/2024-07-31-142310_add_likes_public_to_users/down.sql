@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN likes_public;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE likes DROP COLUMN emoji;
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS mentions;
+DROP TABLE IF EXISTS follows;
+ALTER TABLE posts DROP COLUMN visibility;
\ No newline at end of file
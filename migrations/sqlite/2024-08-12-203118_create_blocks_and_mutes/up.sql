@@ -0,0 +1,19 @@
+CREATE TABLE blocks (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  blocker_id INTEGER NOT NULL,
+  blocked_id INTEGER NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(blocker_id) REFERENCES users(id),
+  FOREIGN KEY(blocked_id) REFERENCES users(id)
+);
+
+CREATE TABLE mutes (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  muter_id INTEGER NOT NULL,
+  muted_id INTEGER NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(muter_id) REFERENCES users(id),
+  FOREIGN KEY(muted_id) REFERENCES users(id)
+);
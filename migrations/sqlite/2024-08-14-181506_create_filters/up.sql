@@ -0,0 +1,13 @@
+CREATE TABLE filters (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  uuid VARCHAR(8) NOT NULL UNIQUE,
+  owner_id INTEGER NOT NULL,
+  phrase VARCHAR(128) NOT NULL,
+  kind VARCHAR(16) NOT NULL,
+  whole_word BOOLEAN NOT NULL DEFAULT TRUE,
+  action VARCHAR(16) NOT NULL,
+  expires_at TIMESTAMP,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  deleted BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY(owner_id) REFERENCES users(id)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE posts ADD COLUMN content_warning VARCHAR(256);
+ALTER TABLE posts ADD COLUMN sensitive_media BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN sensitive_media VARCHAR(8) NOT NULL DEFAULT 'blur';
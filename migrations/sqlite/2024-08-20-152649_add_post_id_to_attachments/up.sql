@@ -0,0 +1 @@
+ALTER TABLE attachments ADD COLUMN post_id INTEGER REFERENCES posts(id);
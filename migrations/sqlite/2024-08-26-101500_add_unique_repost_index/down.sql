@@ -0,0 +1 @@
+DROP INDEX posts_poster_id_repost_of_id;
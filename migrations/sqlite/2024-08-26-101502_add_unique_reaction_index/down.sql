@@ -0,0 +1 @@
+DROP INDEX likes_user_id_post_id_emoji;
@@ -0,0 +1,10 @@
+-- Reacting used to check for an existing reaction before inserting, which let
+-- concurrent requests through, so keep the oldest of any duplicates.
+-- `microblogs-admin recount` fixes the like counts afterwards.
+DELETE FROM likes
+  WHERE deleted = FALSE AND id NOT IN (
+    SELECT MIN(id) FROM likes WHERE deleted = FALSE GROUP BY user_id, post_id, emoji
+  );
+
+CREATE UNIQUE INDEX likes_user_id_post_id_emoji ON likes(user_id, post_id, emoji)
+  WHERE deleted = FALSE;
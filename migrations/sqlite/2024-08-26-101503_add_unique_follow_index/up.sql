@@ -0,0 +1,9 @@
+-- Following used to check for an existing follow before inserting, which let
+-- concurrent requests through, so keep the oldest of any duplicates.
+DELETE FROM follows
+  WHERE deleted = FALSE AND id NOT IN (
+    SELECT MIN(id) FROM follows WHERE deleted = FALSE GROUP BY follower_id, followee_id
+  );
+
+CREATE UNIQUE INDEX follows_follower_id_followee_id ON follows(follower_id, followee_id)
+  WHERE deleted = FALSE;
@@ -11,13 +11,15 @@ use actix_web::{
 };
 use chrono::NaiveDateTime;
 use diesel::{
-    query_dsl::methods::FilterDsl, Connection, ExpressionMethods, Insertable, Queryable,
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, QueryDsl, Queryable,
     RunQueryDsl, Selectable, SelectableHelper,
 };
-use microblogs::{errors::ServiceError, generate_uid, schema, AppState, DbPool};
+use microblogs::{
+    errors::ServiceError, generate_uid, message, schema, AppState, DbConn, DbPool, WritePool,
+};
 use serde::Serialize;
 
-use crate::users::UserDetails;
+use crate::{users::UserDetails, visibility::visible_to};
 
 #[derive(Debug, MultipartForm)]
 struct UploadForm {
@@ -34,7 +36,7 @@ struct NewAttachment {
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = schema::attachments)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(microblogs::DbBackend))]
 struct Attachment {
     pub id: i32,
     pub uploader_id: i32,
@@ -67,15 +69,57 @@ impl From<Attachment> for AttachmentRead {
     }
 }
 
+// Links uploaded attachments to a post. Only unused uploads of the poster can
+// be attached, otherwise the whole operation fails.
+pub fn attach_to_post(
+    target_post_id: i32,
+    target_uploader_id: i32,
+    attachment_uuids: &[String],
+    conn: &mut DbConn,
+) -> Result<(), diesel::result::Error> {
+    use schema::attachments::dsl::*;
+
+    let mut attachment_uuids = attachment_uuids.to_vec();
+    attachment_uuids.sort();
+    attachment_uuids.dedup();
+    if attachment_uuids.is_empty() {
+        return Ok(());
+    }
+
+    let attached = diesel::update(attachments)
+        .filter(
+            uuid.eq_any(&attachment_uuids)
+                .and(uploader_id.eq(target_uploader_id))
+                .and(post_id.is_null())
+                .and(deleted.eq(false)),
+        )
+        .set(post_id.eq(target_post_id))
+        .execute(conn)?;
+
+    if attached != attachment_uuids.len() {
+        return Err(diesel::result::Error::RollbackTransaction);
+    }
+
+    Ok(())
+}
+
 #[post("/upload")]
 async fn upload_attachment(
     MultipartForm(form): MultipartForm<UploadForm>,
     app_state: web::Data<AppState>,
-    pool: web::Data<DbPool>,
+    pool: web::Data<WritePool>,
     current_user: UserDetails,
 ) -> Result<HttpResponse, actix_web::Error> {
     use schema::attachments::dsl::*;
 
+    if form.files.len() > app_state.max_upload_files {
+        return Err(ServiceError::BadRequest(
+            "too_many_files",
+            message!("too-many-files", max = app_state.max_upload_files),
+        )
+        .into());
+    }
+
     let mut attachments_to_save: Vec<NewAttachment> = Vec::new();
 
     for file in form.files {
@@ -84,13 +128,20 @@ async fn upload_attachment(
                 // only allow images and videos
                 match content_type.type_().as_str() {
                     "image" | "video" => {}
-                    _ => return Err(ServiceError::BadRequest(format!("Somente imagens e vídeos são permitidos. Um dos arquivos tem o seguinte tipo: {}.", content_type)).into()),
+                    _ => {
+                        return Err(ServiceError::BadRequest(
+                            "unsupported_file_type",
+                            message!("unsupported-file-type", content_type = content_type),
+                        )
+                        .into())
+                    }
                 }
             }
             None => {
-                return Err(ServiceError::BadRequest(format!(
-                    "O cabeçalho \"Content-Type\" não está presente."
-                ))
+                return Err(ServiceError::BadRequest(
+                    "missing_content_type",
+                    message!("missing-content-type"),
+                )
                 .into())
             }
         }
@@ -98,9 +149,10 @@ async fn upload_attachment(
         let fname = match file.file_name {
             Some(fname) => fname.to_string(),
             None => {
-                return Err(ServiceError::BadRequest(format!(
-                    "O cabeçalho \"Content-Disposition\" não está presente."
-                ))
+                return Err(ServiceError::BadRequest(
+                    "missing_content_disposition",
+                    message!("missing-content-disposition"),
+                )
                 .into())
             }
         };
@@ -109,40 +161,40 @@ async fn upload_attachment(
         let stem = match fpath.file_stem() {
             Some(stem) => stem.to_str(),
             None => {
-                return Err(ServiceError::BadRequest(format!(
-                    "Falha ao extrair o nome do arquivo \"{}\".",
-                    fname
-                ))
+                return Err(ServiceError::BadRequest(
+                    "invalid_file_name",
+                    message!("file-name-not-extracted", file = fname),
+                )
                 .into())
             }
         };
         let stem = match stem {
             Some(stem) => stem,
             None => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Falha ao converter o nome do arquivo \"{}\".",
-                    fname
-                ))
+                return Err(ServiceError::InternalServerError(
+                    "internal_error",
+                    message!("file-name-not-converted", file = fname),
+                )
                 .into())
             }
         };
         let extension = match fpath.extension() {
             Some(extension) => extension,
             None => {
-                return Err(ServiceError::BadRequest(format!(
-                    "Falha ao extrair a extensão do arquivo \"{}\".",
-                    fname
-                ))
+                return Err(ServiceError::BadRequest(
+                    "invalid_file_extension",
+                    message!("file-extension-not-extracted", file = fname),
+                )
                 .into())
             }
         };
         let extension = match extension.to_str() {
             Some(extension) => extension,
             None => {
-                return Err(ServiceError::BadRequest(format!(
-                    "Falha ao converter a extensão do arquivo \"{}\".",
-                    fname
-                ))
+                return Err(ServiceError::BadRequest(
+                    "invalid_file_extension",
+                    message!("file-extension-not-converted", file = fname),
+                )
                 .into())
             }
         };
@@ -154,11 +206,11 @@ async fn upload_attachment(
             app_state.uploads_dir, attachment_uuid, saved_file_name
         );
 
-        if let Err(_) = create_dir(format!("{}/{}", app_state.uploads_dir, attachment_uuid)) {
-            return Err(ServiceError::InternalServerError(format!(
-                "Não foi possível criar o diretório para o arquivo \"{}\".",
-                fname
-            ))
+        if create_dir(format!("{}/{}", app_state.uploads_dir, attachment_uuid)).is_err() {
+            return Err(ServiceError::InternalServerError(
+                "internal_error",
+                message!("file-directory-not-created", file = fname),
+            )
             .into());
         };
 
@@ -173,10 +225,10 @@ async fn upload_attachment(
                 attachments_to_save.push(new_attachment);
             }
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível salvar o arquivo \"{}\".",
-                    fname
-                ))
+                return Err(ServiceError::InternalServerError(
+                    "internal_error",
+                    message!("file-not-saved", file = fname),
+                )
                 .into())
             }
         }
@@ -186,34 +238,27 @@ async fn upload_attachment(
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
         match conn.transaction::<Vec<AttachmentRead>, diesel::result::Error, _>(|conn| {
             let mut uploaded_attachments: Vec<AttachmentRead> = Vec::new();
             for attachment in attachments_to_save {
-                match diesel::insert_into(attachments)
+                let stored: Attachment = diesel::insert_into(attachments)
                     .values(&attachment)
                     .returning(Attachment::as_returning())
-                    .get_result(conn)
-                {
-                    Ok(attachment) => uploaded_attachments.push(attachment.into()),
-                    Err(_) => return Err(diesel::result::Error::RollbackTransaction),
-                }
+                    .get_result(conn)?;
+                uploaded_attachments.push(stored.into());
             }
 
             Ok(uploaded_attachments)
         }) {
             Ok(result) => Ok(result),
-            Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Um ou mais arquivos não puderam ser adicionados ao banco de dados."
-                ))
-                .into())
-            }
+            Err(err) => Err(err.into()),
         }
     })
     .await??;
@@ -226,32 +271,50 @@ async fn download_attachment(
     attachment_uuid: web::Path<String>,
     app_state: web::Data<AppState>,
     pool: web::Data<DbPool>,
-    _current_user: UserDetails,
+    current_user: UserDetails,
 ) -> Result<NamedFile, actix_web::Error> {
     use schema::attachments::dsl::*;
+    use schema::posts::dsl::{
+        deleted as post_deleted, poster_id, posts, published as post_published,
+    };
 
     let target_attachment_uuid = attachment_uuid.clone();
     let fpath = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
+        // attachments are as visible as their post, and unused uploads only to
+        // their uploader
         let attachment: Attachment = match attachments
-            .filter(uuid.eq(attachment_uuid.as_str()))
+            .left_join(posts)
+            .filter(uuid.eq(attachment_uuid.as_str()).and(deleted.eq(false)))
+            .filter(
+                post_id
+                    .is_null()
+                    .and(uploader_id.eq(current_user.id))
+                    .or(post_deleted
+                        .eq(false)
+                        .and(post_published.eq(true).or(poster_id.eq(current_user.id)))
+                        .and(visible_to(current_user.id))),
+            )
+            .select(Attachment::as_select())
             .first(&mut conn)
         {
             Ok(attachment) => attachment,
-            Err(_) => {
-                return Err(ServiceError::NotFound(format!(
-                    "Anexo \"{}\" não encontrado.",
-                    attachment_uuid.clone()
-                )))
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::NotFound(
+                    "attachment_not_found",
+                    message!("attachment-not-found", uuid = attachment_uuid.clone()),
+                ))
             }
+            Err(err) => return Err(err.into()),
         };
 
         let path = format!(
@@ -274,10 +337,10 @@ async fn download_attachment(
                     parameters: vec![],
                 }))
         }
-        Err(_) => Err(ServiceError::InternalServerError(format!(
-            "Não foi possível abrir o arquivo do anexo \"{}\".",
-            target_attachment_uuid
-        ))
+        Err(_) => Err(ServiceError::InternalServerError(
+            "internal_error",
+            message!("attachment-not-opened", uuid = target_attachment_uuid),
+        )
         .into()),
     }
 }
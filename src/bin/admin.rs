@@ -0,0 +1,403 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    time::{Duration, SystemTime},
+};
+
+use chrono::NaiveDateTime;
+use clap::{Parser, Subcommand};
+use diesel::{
+    sql_types::Text, ExpressionMethods, Insertable, JoinOnDsl, NullableExpressionMethods,
+    OptionalExtension, QueryDsl, RunQueryDsl,
+};
+use dotenvy::dotenv;
+use microblogs::{
+    build_pool,
+    config::{Config, ConfigOverrides},
+    hash_password, schema,
+    text::{is_valid_email, is_valid_username, MAX_USERNAME_LENGTH},
+    DbConn, LIKE_REACTION,
+};
+
+type AdminResult = Result<(), Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(version, about = "Manages a microblogs instance")]
+struct Cli {
+    /// Path to the TOML configuration file [default: microblogs.toml, if present]
+    #[arg(short, long, env = "MICROBLOGS_CONFIG")]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a user
+    CreateUser {
+        username: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        real_name: String,
+        #[arg(long, default_value = "")]
+        summary: String,
+        /// Read from the standard input when not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Set a new password for a user
+    ResetPassword {
+        username: String,
+        /// Read from the standard input when not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Soft-delete a user
+    DeleteUser { username: String },
+    /// Restore a soft-deleted user
+    RestoreUser { username: String },
+    /// Soft-delete a post
+    DeletePost { uuid: String },
+    /// Restore a soft-deleted post
+    RestorePost { uuid: String },
+    /// Recompute the reply, quote, repost and like counts of every post
+    Recount,
+    /// List the attachments uploaded by a user
+    ListAttachments { username: String },
+    /// Remove the upload directories no attachment refers to anymore
+    PurgeUploads {
+        /// Only list the directories that would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Keep directories modified within this many hours, as an upload
+        /// creates its directory before the attachment is saved
+        #[arg(long, default_value_t = 24)]
+        min_age_hours: u64,
+    },
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::users)]
+struct NewUser<'a> {
+    username: &'a str,
+    email: &'a str,
+    real_name: &'a str,
+    summary: &'a str,
+    password: &'a str,
+}
+
+fn read_password(password: Option<String>) -> Result<String, Box<dyn Error>> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            eprint!("Password: ");
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    if password.is_empty() {
+        return Err("the password can't be empty".into());
+    }
+    Ok(password)
+}
+
+fn find_user_id(target_username: &str, conn: &mut DbConn) -> Result<i32, Box<dyn Error>> {
+    use schema::users::dsl::{id, username, users};
+
+    users
+        .filter(username.eq(target_username))
+        .select(id)
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| format!("user {} not found", target_username).into())
+}
+
+fn create_user(
+    target_username: &str,
+    email: &str,
+    real_name: &str,
+    summary: &str,
+    password: Option<String>,
+    conn: &mut DbConn,
+) -> AdminResult {
+    use schema::users::dsl::users;
+
+    if !is_valid_username(target_username) {
+        return Err(format!(
+            "the username must have from 1 to {} letters, numbers or underscores",
+            MAX_USERNAME_LENGTH
+        )
+        .into());
+    }
+    if !is_valid_email(email) {
+        return Err(format!("{} is not a valid email address", email).into());
+    }
+
+    let password = hash_password(&read_password(password)?).map_err(|err| err.to_string())?;
+    diesel::insert_into(users)
+        .values(&NewUser {
+            username: target_username,
+            email,
+            real_name,
+            summary,
+            password: &password,
+        })
+        .execute(conn)?;
+
+    println!("Created user {}", target_username);
+    Ok(())
+}
+
+fn reset_password(
+    target_username: &str,
+    password: Option<String>,
+    conn: &mut DbConn,
+) -> AdminResult {
+    use schema::users::dsl::{password as user_password, users};
+
+    let user_id = find_user_id(target_username, conn)?;
+    let password = hash_password(&read_password(password)?).map_err(|err| err.to_string())?;
+    diesel::update(users.find(user_id))
+        .set(user_password.eq(password))
+        .execute(conn)?;
+
+    println!("Changed the password of {}", target_username);
+    Ok(())
+}
+
+fn set_user_deleted(target_username: &str, is_deleted: bool, conn: &mut DbConn) -> AdminResult {
+    use schema::users::dsl::{deleted, username, users};
+
+    let updated = diesel::update(users.filter(username.eq(target_username)))
+        .set(deleted.eq(is_deleted))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(format!("user {} not found", target_username).into());
+    }
+
+    let action = if is_deleted { "Deleted" } else { "Restored" };
+    println!("{} user {}", action, target_username);
+    Ok(())
+}
+
+fn set_post_deleted(target_uuid: &str, is_deleted: bool, conn: &mut DbConn) -> AdminResult {
+    use schema::posts::dsl::{deleted, posts, uuid};
+
+    let updated = diesel::update(posts.filter(uuid.eq(target_uuid)))
+        .set(deleted.eq(is_deleted))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(format!("post {} not found", target_uuid).into());
+    }
+
+    let action = if is_deleted { "Deleted" } else { "Restored" };
+    println!("{} post {}", action, target_uuid);
+    Ok(())
+}
+
+// Rebuilds the counters handlers keep up to date incrementally, which drift
+// when rows are changed by hand or a request fails halfway.
+fn recount(conn: &mut DbConn) -> AdminResult {
+    let updated = diesel::sql_query(
+        "UPDATE posts SET
+            reply_count = (
+                SELECT COUNT(*) FROM posts AS replies
+                WHERE replies.parent_id = posts.id
+                    AND replies.published = TRUE AND replies.deleted = FALSE
+            ),
+            quote_count = (
+                SELECT COUNT(*) FROM posts AS quotes
+                WHERE quotes.quote_of_id = posts.id
+                    AND quotes.published = TRUE AND quotes.deleted = FALSE
+            ),
+            repost_count = (
+                SELECT COUNT(*) FROM posts AS reposts
+                WHERE reposts.repost_of_id = posts.id AND reposts.deleted = FALSE
+            ),
+            like_count = (
+                SELECT COUNT(*) FROM likes
+                WHERE likes.post_id = posts.id
+                    AND likes.emoji = $1 AND likes.deleted = FALSE
+            )",
+    )
+    .bind::<Text, _>(LIKE_REACTION)
+    .execute(conn)?;
+
+    println!("Recounted {} posts", updated);
+    Ok(())
+}
+
+fn list_attachments(target_username: &str, conn: &mut DbConn) -> AdminResult {
+    use schema::attachments::dsl::{
+        attachments, deleted, file_name, post_id, uploaded_at, uploader_id, uuid,
+    };
+    use schema::posts::dsl::{id as post_table_id, posts, uuid as post_uuid};
+
+    let user_id = find_user_id(target_username, conn)?;
+    let found: Vec<(String, String, NaiveDateTime, bool, Option<String>)> = attachments
+        .left_join(posts.on(post_table_id.nullable().eq(post_id)))
+        .filter(uploader_id.eq(user_id))
+        .select((uuid, file_name, uploaded_at, deleted, post_uuid.nullable()))
+        .order_by(uploaded_at.desc())
+        .load(conn)?;
+
+    println!("uuid\tuploaded at\tpost\tdeleted\tfile");
+    for (attachment_uuid, name, uploaded, is_deleted, attached_to) in found {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            attachment_uuid,
+            uploaded,
+            attached_to.as_deref().unwrap_or("-"),
+            if is_deleted { "yes" } else { "no" },
+            name
+        );
+    }
+    Ok(())
+}
+
+// Every attachment is stored in a directory named after its uuid, so the ones
+// without a live attachment are left over from failed or deleted uploads.
+// Recent directories may belong to uploads still in progress and are kept.
+fn purge_uploads(
+    uploads_dir: &str,
+    dry_run: bool,
+    min_age: Duration,
+    conn: &mut DbConn,
+) -> AdminResult {
+    use schema::attachments::dsl::{attachments, deleted, uuid};
+
+    let live: HashSet<String> = attachments
+        .filter(deleted.eq(false))
+        .select(uuid)
+        .load::<String>(conn)?
+        .into_iter()
+        .collect();
+
+    let mut purged = 0;
+    for entry in fs::read_dir(uploads_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if live.contains(&name) {
+            continue;
+        }
+        let age = SystemTime::now()
+            .duration_since(entry.metadata()?.modified()?)
+            .unwrap_or_default();
+        if age < min_age {
+            continue;
+        }
+
+        if dry_run {
+            println!("Would remove {}", entry.path().display());
+        } else {
+            fs::remove_dir_all(entry.path())?;
+            println!("Removed {}", entry.path().display());
+        }
+        purged += 1;
+    }
+
+    if purged == 0 {
+        println!("No orphaned uploads");
+    }
+    Ok(())
+}
+
+fn run(cli: Cli) -> AdminResult {
+    let config = Config::load(cli.config.as_deref(), cli.overrides)?;
+    let pool = build_pool(&config.database.url, 1)?;
+    let mut conn = pool.get()?;
+
+    match cli.command {
+        Command::CreateUser {
+            username,
+            email,
+            real_name,
+            summary,
+            password,
+        } => create_user(&username, &email, &real_name, &summary, password, &mut conn),
+        Command::ResetPassword { username, password } => {
+            reset_password(&username, password, &mut conn)
+        }
+        Command::DeleteUser { username } => set_user_deleted(&username, true, &mut conn),
+        Command::RestoreUser { username } => set_user_deleted(&username, false, &mut conn),
+        Command::DeletePost { uuid } => set_post_deleted(&uuid, true, &mut conn),
+        Command::RestorePost { uuid } => set_post_deleted(&uuid, false, &mut conn),
+        Command::Recount => recount(&mut conn),
+        Command::ListAttachments { username } => list_attachments(&username, &mut conn),
+        Command::PurgeUploads {
+            dry_run,
+            min_age_hours,
+        } => purge_uploads(
+            &config.uploads.dir,
+            dry_run,
+            Duration::from_secs(min_age_hours * 60 * 60),
+            &mut conn,
+        ),
+    }
+}
+
+fn main() {
+    dotenv().ok();
+
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use microblogs::test_connection;
+
+    use super::*;
+
+    #[test]
+    fn recount_rebuilds_post_counters() {
+        use schema::posts::dsl::{id, like_count, posts, quote_count, reply_count, repost_count};
+
+        let mut conn = test_connection();
+        conn.batch_execute(
+            "INSERT INTO users (id, username, email, real_name, summary, password) VALUES
+                 (1, 'a', 'a@example.com', 'A', '', 'x'),
+                 (2, 'b', 'b@example.com', 'B', '', 'x');
+             INSERT INTO posts
+                 (id, uuid, parent_id, repost_of_id, quote_of_id, poster_id, body,
+                  published, deleted, reply_count, like_count)
+             VALUES
+                 (1, 'p1', NULL, NULL, NULL, 1, 'post', TRUE, FALSE, 7, 7),
+                 (2, 'p2', 1, NULL, NULL, 2, 'reply', TRUE, FALSE, 0, 0),
+                 (3, 'p3', 1, NULL, NULL, 2, 'deleted reply', TRUE, TRUE, 0, 0),
+                 (4, 'p4', 1, NULL, NULL, 2, 'draft reply', FALSE, FALSE, 0, 0),
+                 (5, 'p5', NULL, 1, NULL, 2, '', TRUE, FALSE, 0, 0),
+                 (6, 'p6', NULL, NULL, 1, 2, 'quote', TRUE, FALSE, 0, 0);
+             INSERT INTO likes (user_id, post_id, emoji, deleted) VALUES
+                 (2, 1, '❤️', FALSE),
+                 (2, 1, '🎉', FALSE),
+                 (1, 1, '❤️', TRUE);",
+        )
+        .unwrap();
+
+        recount(&mut conn).unwrap();
+
+        let counts: (i32, i32, i32, i32) = posts
+            .filter(id.eq(1))
+            .select((reply_count, quote_count, repost_count, like_count))
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(counts, (1, 1, 1, 1));
+    }
+}
@@ -0,0 +1,203 @@
+use actix_web::{
+    delete, get, post,
+    web::{self, ServiceConfig},
+    Error, HttpResponse,
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, QueryDsl, Queryable,
+    RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{errors::ServiceError, generate_uid, message, schema, DbPool, WritePool};
+use serde::{Deserialize, Serialize};
+
+use crate::users::UserDetails;
+
+#[derive(Deserialize)]
+struct CollectionCreate {
+    name: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::bookmark_collections)]
+struct NewCollection<'a> {
+    pub uuid: String,
+    pub owner_id: i32,
+    pub name: &'a str,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::bookmark_collections)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+pub struct BookmarkCollection {
+    pub id: i32,
+    pub uuid: String,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct CollectionRead {
+    uuid: String,
+    name: String,
+    created_at: String,
+}
+
+impl From<BookmarkCollection> for CollectionRead {
+    fn from(collection: BookmarkCollection) -> Self {
+        CollectionRead {
+            uuid: collection.uuid,
+            name: collection.name,
+            created_at: collection.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CollectionsRead {
+    collections: Vec<CollectionRead>,
+}
+
+#[post("/collections")]
+async fn create_collection(
+    info: web::Json<CollectionCreate>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::bookmark_collections::dsl::*;
+
+    let collection = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let new_collection = NewCollection {
+            uuid: generate_uid(),
+            owner_id: current_user.id,
+            name: info.name.trim(),
+        };
+
+        if new_collection.name.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "empty_collection_name",
+                message!("empty-collection-name"),
+            ));
+        }
+
+        match diesel::insert_into(bookmark_collections)
+            .values(&new_collection)
+            .returning(BookmarkCollection::as_returning())
+            .get_result(&mut conn)
+        {
+            Ok(collection) => Ok(collection),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(CollectionRead::from(collection)))
+}
+
+#[get("/collections")]
+async fn list_collections(
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::bookmark_collections::dsl::*;
+
+    let collections = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match bookmark_collections
+            .filter(owner_id.eq(current_user.id).and(deleted.eq(false)))
+            .select(BookmarkCollection::as_select())
+            .order_by(name.asc())
+            .load(&mut conn)
+        {
+            Ok(collections) => Ok(collections),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(CollectionsRead {
+        collections: collections.into_iter().map(CollectionRead::from).collect(),
+    }))
+}
+
+#[delete("/collections/{target_collection_uuid}")]
+async fn delete_collection(
+    target_collection_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::bookmark_collections::dsl::{bookmark_collections, deleted, owner_id, uuid};
+    use schema::bookmarks::dsl::{bookmarks, collection_id as bookmark_collection_id};
+
+    let collection = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<BookmarkCollection, diesel::result::Error, _>(|conn| {
+            let collection = diesel::update(bookmark_collections)
+                .filter(
+                    uuid.eq(target_collection_uuid.as_str())
+                        .and(owner_id.eq(current_user.id))
+                        .and(deleted.eq(false)),
+                )
+                .set(deleted.eq(true))
+                .returning(BookmarkCollection::as_returning())
+                .get_result(conn)?;
+
+            // bookmarks in the collection are kept, just uncategorized
+            diesel::update(bookmarks)
+                .filter(bookmark_collection_id.eq(collection.id))
+                .set(bookmark_collection_id.eq(None::<i32>))
+                .execute(conn)?;
+
+            Ok(collection)
+        });
+
+        match result {
+            Ok(collection) => Ok(collection),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "collection_not_found",
+                message!("collection-not-found", uuid = target_collection_uuid),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(CollectionRead::from(collection)))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/bookmarks")
+            .service(create_collection)
+            .service(list_collections)
+            .service(delete_collection),
+    );
+}
@@ -0,0 +1,595 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use serde::Deserialize;
+
+use crate::{
+    DEFAULT_LINK_PREVIEW_MAX_PAGE_SIZE, DEFAULT_LINK_PREVIEW_TIMEOUT, DEFAULT_MAX_PINNED_POSTS,
+    DEFAULT_MAX_POST_LENGTH, DEFAULT_REACTIONS, LIKE_REACTION, MAX_POST_BODY_CHARS,
+};
+
+// Read when no configuration file is given and it exists in the working
+// directory.
+pub const DEFAULT_CONFIG_PATH: &str = "microblogs.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => {
+                write!(f, "could not read {}: {}", path.display(), err)
+            }
+            ConfigError::Parse(path, err) => write!(f, "invalid {}: {}", path.display(), err),
+            ConfigError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub uploads: UploadsConfig,
+    pub posts: PostsConfig,
+    pub link_previews: LinkPreviewsConfig,
+    pub features: FeaturesConfig,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    // defaults to the number of CPUs when not set
+    pub workers: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "0.0.0.0:8080".to_string(),
+            workers: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub secret_key: String,
+    pub token_lifetime_hours: i64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            secret_key: String::new(),
+            token_lifetime_hours: 24,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadsConfig {
+    pub dir: String,
+    // in bytes, for all the files of a single upload together
+    pub max_size: usize,
+    pub max_files: usize,
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        UploadsConfig {
+            dir: "uploads".to_string(),
+            max_size: 50 * 1024 * 1024,
+            max_files: 4,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostsConfig {
+    pub max_length: usize,
+    pub max_pinned: i64,
+    pub reactions: Vec<String>,
+}
+
+impl Default for PostsConfig {
+    fn default() -> Self {
+        PostsConfig {
+            max_length: DEFAULT_MAX_POST_LENGTH,
+            max_pinned: DEFAULT_MAX_PINNED_POSTS,
+            reactions: DEFAULT_REACTIONS.split(',').map(String::from).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkPreviewsConfig {
+    pub timeout_secs: u64,
+    pub max_page_size: usize,
+    pub allow_private_hosts: bool,
+}
+
+impl Default for LinkPreviewsConfig {
+    fn default() -> Self {
+        LinkPreviewsConfig {
+            timeout_secs: DEFAULT_LINK_PREVIEW_TIMEOUT.as_secs(),
+            max_page_size: DEFAULT_LINK_PREVIEW_MAX_PAGE_SIZE,
+            allow_private_hosts: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    pub registrations: bool,
+    pub link_previews: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        FeaturesConfig {
+            registrations: true,
+            link_previews: true,
+        }
+    }
+}
+
+// Settings that can be given on the command line or through the environment,
+// overriding the ones from the configuration file.
+#[derive(Args, Default)]
+pub struct ConfigOverrides {
+    /// Address to listen on, as host:port
+    #[arg(long, env = "BIND_ADDRESS")]
+    pub bind: Option<String>,
+    /// Number of worker threads
+    #[arg(long, env = "WORKERS")]
+    pub workers: Option<usize>,
+    /// Database URL
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+    /// Maximum number of database connections
+    #[arg(long, env = "DATABASE_POOL_SIZE")]
+    pub pool_size: Option<u32>,
+    /// Key used to sign access tokens
+    #[arg(long, env = "SECRET_KEY", hide_env_values = true)]
+    pub secret_key: Option<String>,
+    /// Hours an access token stays valid
+    #[arg(long, env = "TOKEN_LIFETIME_HOURS")]
+    pub token_lifetime_hours: Option<i64>,
+    /// Origins allowed to call the API, separated by commas
+    #[arg(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+    // FRONTEND_ORIGIN was the only way to set the allowed origin before the
+    // configuration file, so deployments relying on it keep working
+    #[arg(long, env = "FRONTEND_ORIGIN", hide = true)]
+    pub frontend_origin: Option<String>,
+    /// Directory where attachments are stored
+    #[arg(long, env = "UPLOADS_DIR")]
+    pub uploads_dir: Option<String>,
+    /// Maximum size in bytes of a single upload
+    #[arg(long, env = "MAX_UPLOAD_SIZE")]
+    pub max_upload_size: Option<usize>,
+    /// Maximum number of files in a single upload
+    #[arg(long, env = "MAX_UPLOAD_FILES")]
+    pub max_upload_files: Option<usize>,
+    /// Maximum number of characters in a post
+    #[arg(long, env = "MAX_POST_LENGTH")]
+    pub max_post_length: Option<usize>,
+    /// Maximum number of posts a user can pin
+    #[arg(long, env = "MAX_PINNED_POSTS")]
+    pub max_pinned_posts: Option<i64>,
+    /// Reactions available on the instance, separated by commas
+    #[arg(long, env = "REACTION_EMOJIS", value_delimiter = ',')]
+    pub reactions: Option<Vec<String>>,
+    /// Whether new users can register
+    #[arg(long, env = "REGISTRATIONS_ENABLED")]
+    pub registrations: Option<bool>,
+    /// Whether link previews are fetched for new posts
+    #[arg(long, env = "LINK_PREVIEWS_ENABLED")]
+    pub link_previews: Option<bool>,
+    /// Seconds to wait for a page when fetching a link preview
+    #[arg(long, env = "LINK_PREVIEW_TIMEOUT")]
+    pub link_preview_timeout: Option<u64>,
+    /// Maximum size in bytes of a page fetched for a link preview
+    #[arg(long, env = "LINK_PREVIEW_MAX_PAGE_SIZE")]
+    pub link_preview_max_page_size: Option<usize>,
+    /// Whether link previews can be fetched from private addresses
+    #[arg(long, env = "LINK_PREVIEW_ALLOW_PRIVATE_HOSTS")]
+    pub link_preview_allow_private_hosts: Option<bool>,
+}
+
+impl Config {
+    // Reads the configuration file, if any, applies the overrides on top of it
+    // and checks the result.
+    pub fn load(path: Option<&Path>, overrides: ConfigOverrides) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|path| path.exists()),
+        };
+
+        let mut config = match path {
+            Some(path) => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|err| ConfigError::Read(path.clone(), err))?;
+                toml::from_str(&contents).map_err(|err| ConfigError::Parse(path, err))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply(overrides);
+        config.normalize();
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply(&mut self, overrides: ConfigOverrides) {
+        fn set<T>(target: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *target = value;
+            }
+        }
+
+        set(&mut self.server.bind, overrides.bind);
+        if overrides.workers.is_some() {
+            self.server.workers = overrides.workers;
+        }
+        set(&mut self.database.url, overrides.database_url);
+        set(&mut self.database.pool_size, overrides.pool_size);
+        set(&mut self.auth.secret_key, overrides.secret_key);
+        set(
+            &mut self.auth.token_lifetime_hours,
+            overrides.token_lifetime_hours,
+        );
+        set(&mut self.cors.allowed_origins, overrides.cors_origins);
+        if self.cors.allowed_origins.is_empty() {
+            if let Some(origin) = overrides.frontend_origin {
+                self.cors.allowed_origins.push(origin);
+            }
+        }
+        set(&mut self.uploads.dir, overrides.uploads_dir);
+        set(&mut self.uploads.max_size, overrides.max_upload_size);
+        set(&mut self.uploads.max_files, overrides.max_upload_files);
+        set(&mut self.posts.max_length, overrides.max_post_length);
+        set(&mut self.posts.max_pinned, overrides.max_pinned_posts);
+        set(&mut self.posts.reactions, overrides.reactions);
+        set(&mut self.features.registrations, overrides.registrations);
+        set(&mut self.features.link_previews, overrides.link_previews);
+        set(
+            &mut self.link_previews.timeout_secs,
+            overrides.link_preview_timeout,
+        );
+        set(
+            &mut self.link_previews.max_page_size,
+            overrides.link_preview_max_page_size,
+        );
+        set(
+            &mut self.link_previews.allow_private_hosts,
+            overrides.link_preview_allow_private_hosts,
+        );
+    }
+
+    fn normalize(&mut self) {
+        for origin in self.cors.allowed_origins.iter_mut() {
+            *origin = origin.trim().trim_end_matches('/').to_string();
+        }
+        self.cors
+            .allowed_origins
+            .retain(|origin| !origin.is_empty());
+
+        for reaction in self.posts.reactions.iter_mut() {
+            *reaction = reaction.trim().to_string();
+        }
+        self.posts.reactions.retain(|reaction| !reaction.is_empty());
+        // the like reaction is always available so `/posts/like` keeps working
+        if !self
+            .posts
+            .reactions
+            .iter()
+            .any(|reaction| reaction == LIKE_REACTION)
+        {
+            self.posts.reactions.insert(0, LIKE_REACTION.to_string());
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        fn invalid(reason: String) -> Result<(), ConfigError> {
+            Err(ConfigError::Invalid(reason))
+        }
+
+        if self.database.url.is_empty() {
+            return invalid(
+                "database.url is required (or --database-url, DATABASE_URL)".to_string(),
+            );
+        }
+        if self.uploads.dir.is_empty() {
+            return invalid("uploads.dir can't be empty".to_string());
+        }
+        if let Err(err) = self.server.bind.to_socket_addrs() {
+            return invalid(format!(
+                "server.bind must be a host:port address, got \"{}\": {}",
+                self.server.bind, err
+            ));
+        }
+
+        for (name, value) in [
+            ("server.workers", self.server.workers.unwrap_or(1) as i64),
+            ("database.pool_size", self.database.pool_size as i64),
+            ("auth.token_lifetime_hours", self.auth.token_lifetime_hours),
+            ("uploads.max_size", self.uploads.max_size as i64),
+            ("uploads.max_files", self.uploads.max_files as i64),
+            ("posts.max_length", self.posts.max_length as i64),
+            (
+                "link_previews.timeout_secs",
+                self.link_previews.timeout_secs as i64,
+            ),
+            (
+                "link_previews.max_page_size",
+                self.link_previews.max_page_size as i64,
+            ),
+        ] {
+            if value <= 0 {
+                return invalid(format!("{} must be positive, got {}", name, value));
+            }
+        }
+        if self.posts.max_length > MAX_POST_BODY_CHARS {
+            return invalid(format!(
+                "posts.max_length can't be greater than {}, got {}",
+                MAX_POST_BODY_CHARS, self.posts.max_length
+            ));
+        }
+        if self.posts.max_pinned < 0 {
+            return invalid(format!(
+                "posts.max_pinned can't be negative, got {}",
+                self.posts.max_pinned
+            ));
+        }
+
+        for origin in &self.cors.allowed_origins {
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"));
+            let malformed = match host {
+                Some(host) => {
+                    host.is_empty() || host.contains(|c: char| c == '/' || c.is_whitespace())
+                }
+                None => true,
+            };
+            if malformed {
+                return invalid(format!(
+                    "cors.allowed_origins must contain origins like https://example.com, got \"{}\"",
+                    origin
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Settings only the server needs, so commands like `migrate` can run with
+    // just the database configured.
+    pub fn check_server(&self) -> Result<(), ConfigError> {
+        if self.auth.secret_key.is_empty() {
+            return Err(ConfigError::Invalid(
+                "auth.secret_key is required (or --secret-key, SECRET_KEY)".to_string(),
+            ));
+        }
+        // without an allowed origin browsers can't call the API at all
+        if self.cors.allowed_origins.is_empty() {
+            return Err(ConfigError::Invalid(
+                "cors.allowed_origins is required (or --cors-origin, CORS_ORIGINS)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    }
+
+    fn overrides() -> ConfigOverrides {
+        ConfigOverrides {
+            database_url: Some("postgres://localhost/microblogs".to_string()),
+            ..ConfigOverrides::default()
+        }
+    }
+
+    // Loads `contents` as the configuration file of the test called `name`.
+    fn load(name: &str, contents: &str, overrides: ConfigOverrides) -> Result<Config, ConfigError> {
+        let path = env::temp_dir().join(format!("microblogs-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let config = Config::load(Some(&path), overrides);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    fn load_error(name: &str, contents: &str, overrides: ConfigOverrides) -> String {
+        match load(name, contents, overrides) {
+            Ok(_) => panic!("loaded an invalid configuration"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn overrides_file_with_env_and_env_with_cli() {
+        let contents = "[link_previews]\nmax_page_size = 100\ntimeout_secs = 7\n\n\
+                        [uploads]\nmax_files = 2\n";
+        // the only test reading these variables, so it can set them safely
+        env::set_var("LINK_PREVIEW_MAX_PAGE_SIZE", "200");
+        env::set_var("MAX_UPLOAD_FILES", "3");
+        let from_env = Cli::parse_from(["microblogs"]).overrides;
+        let from_cli =
+            Cli::parse_from(["microblogs", "--link-preview-max-page-size", "300"]).overrides;
+        env::remove_var("LINK_PREVIEW_MAX_PAGE_SIZE");
+        env::remove_var("MAX_UPLOAD_FILES");
+
+        let from_file = load("file", contents, overrides()).unwrap();
+        assert_eq!(from_file.link_previews.max_page_size, 100);
+        assert_eq!(from_file.link_previews.timeout_secs, 7);
+        assert_eq!(from_file.uploads.max_files, 2);
+
+        let config = load(
+            "env",
+            contents,
+            ConfigOverrides {
+                database_url: overrides().database_url,
+                ..from_env
+            },
+        )
+        .unwrap();
+        assert_eq!(config.link_previews.max_page_size, 200);
+        assert_eq!(config.link_previews.timeout_secs, 7);
+        assert_eq!(config.uploads.max_files, 3);
+
+        let config = load(
+            "cli",
+            contents,
+            ConfigOverrides {
+                database_url: overrides().database_url,
+                ..from_cli
+            },
+        )
+        .unwrap();
+        assert_eq!(config.link_previews.max_page_size, 300);
+        assert_eq!(config.uploads.max_files, 3);
+    }
+
+    #[test]
+    fn falls_back_to_frontend_origin() {
+        let config = load(
+            "frontend-origin",
+            "",
+            ConfigOverrides {
+                frontend_origin: Some("https://app.example.com/".to_string()),
+                ..overrides()
+            },
+        )
+        .unwrap();
+        assert_eq!(config.cors.allowed_origins, vec!["https://app.example.com"]);
+
+        let config = load(
+            "cors-origins",
+            "[cors]\nallowed_origins = [\"https://example.com\"]\n",
+            ConfigOverrides {
+                frontend_origin: Some("https://app.example.com".to_string()),
+                ..overrides()
+            },
+        )
+        .unwrap();
+        assert_eq!(config.cors.allowed_origins, vec!["https://example.com"]);
+    }
+
+    #[test]
+    fn requires_a_secret_key_and_an_allowed_origin_to_serve() {
+        let config = load("server", "", overrides()).unwrap();
+        assert!(config.check_server().is_err());
+
+        let config = load(
+            "secret-key",
+            "[auth]\nsecret_key = \"secret\"\n",
+            overrides(),
+        )
+        .unwrap();
+        let err = config.check_server().unwrap_err().to_string();
+        assert!(
+            err.starts_with("cors.allowed_origins is required"),
+            "{}",
+            err
+        );
+
+        let config = load(
+            "cors",
+            "[cors]\nallowed_origins = [\"https://example.com\"]\n",
+            overrides(),
+        )
+        .unwrap();
+        let err = config.check_server().unwrap_err().to_string();
+        assert!(err.starts_with("auth.secret_key is required"), "{}", err);
+
+        let config = load(
+            "complete",
+            "[auth]\nsecret_key = \"secret\"\n\n\
+             [cors]\nallowed_origins = [\"https://example.com\"]\n",
+            overrides(),
+        )
+        .unwrap();
+        assert!(config.check_server().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let err = load_error("database-url", "", ConfigOverrides::default());
+        assert!(err.starts_with("database.url is required"));
+
+        let err = load_error(
+            "malformed-origin",
+            "[cors]\nallowed_origins = [\"example.com\"]\n",
+            overrides(),
+        );
+        assert!(err.starts_with("cors.allowed_origins must contain"));
+
+        let err = load_error(
+            "page-size",
+            "",
+            ConfigOverrides {
+                link_preview_max_page_size: Some(0),
+                ..overrides()
+            },
+        );
+        assert!(err.starts_with("link_previews.max_page_size must be positive"));
+    }
+}
@@ -0,0 +1,696 @@
+use std::time::Duration;
+
+use actix_web::{
+    delete, get, post, put,
+    web::{self, ServiceConfig},
+    Error, HttpResponse,
+};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, QueryDsl, Queryable,
+    RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{
+    errors::{FieldError, ServiceError},
+    generate_uid,
+    hub::EventHub,
+    message, schema, AppState, DbConn, DbPool, Pagination, WritePool,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attachments::attach_to_post,
+    link_previews::fetch_in_background,
+    polls::{create_poll, postpone_poll, PollCreate},
+    posts::{publish_created, record_mentions, validate_content, NewPost, Post},
+    relationships::blocked_between,
+    users::UserDetails,
+    visibility::{visible_to, Visibility},
+};
+
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct DraftCreate {
+    parent_uuid: Option<String>,
+    quote_uuid: Option<String>,
+    body: String,
+    poll: Option<PollCreate>,
+    publish_at: Option<NaiveDateTime>,
+    visibility: Option<Visibility>,
+    content_warning: Option<String>,
+    #[serde(default)]
+    sensitive_media: bool,
+    #[serde(default)]
+    attachments: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DraftUpdate {
+    body: String,
+    publish_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::posts)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct Draft {
+    pub id: i32,
+    pub uuid: String,
+    pub poster_id: i32,
+    pub parent_id: Option<i32>,
+    pub quote_of_id: Option<i32>,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+    pub publish_at: Option<NaiveDateTime>,
+    pub publish_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DraftRead {
+    uuid: String,
+    body: String,
+    created_at: String,
+    publish_at: Option<String>,
+    // why the draft could not be published when it was due
+    publish_error: Option<String>,
+}
+
+impl From<Draft> for DraftRead {
+    fn from(draft: Draft) -> Self {
+        DraftRead {
+            uuid: draft.uuid,
+            body: draft.body,
+            created_at: draft.created_at.to_string(),
+            publish_at: draft.publish_at.map(|publish_at| publish_at.to_string()),
+            publish_error: draft.publish_error,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DraftsRead {
+    drafts: Vec<DraftRead>,
+}
+
+#[derive(Serialize)]
+struct PublishedRead {
+    uuid: String,
+}
+
+fn validate_publish_at(publish_at: &Option<NaiveDateTime>) -> Result<(), ServiceError> {
+    match publish_at {
+        Some(publish_at) if *publish_at <= Utc::now().naive_utc() => Err(ServiceError::BadRequest(
+            "publish_at_in_past",
+            message!("publish-at-in-past", publish_at = publish_at),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn find_published_post(
+    target_uuid: &Option<String>,
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Option<Post>, diesel::result::Error> {
+    use schema::posts::dsl::{deleted, posts, published, uuid};
+
+    match target_uuid {
+        Some(target_uuid) => posts
+            .filter(
+                uuid.eq(target_uuid)
+                    .and(deleted.eq(false))
+                    .and(published.eq(true))
+                    .and(visible_to(current_user_id)),
+            )
+            .select(Post::as_select())
+            .first(conn)
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+// Loads a post a draft replies to or quotes, if its poster can still see it.
+fn find_referenced_post(
+    target_id: i32,
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Option<Post>, diesel::result::Error> {
+    use schema::posts::dsl::{deleted, id, posts, published};
+
+    let post = posts
+        .filter(
+            id.eq(target_id)
+                .and(deleted.eq(false))
+                .and(published.eq(true))
+                .and(visible_to(current_user_id)),
+        )
+        .select(Post::as_select())
+        .first(conn)
+        .optional()?;
+
+    match post {
+        Some(post) if !blocked_between(current_user_id, post.poster_id, conn)? => Ok(Some(post)),
+        _ => Ok(None),
+    }
+}
+
+// Flips a draft into a regular post, applying the side effects `create_post`
+// applies right away to published posts. When the post it replies to or quotes
+// is gone, the draft stays unpublished and keeps the reason in `publish_error`.
+fn publish_draft(
+    draft_id: i32,
+    conn: &mut DbConn,
+) -> Result<(Post, Option<Post>, Option<Post>), ServiceError> {
+    use schema::posts::dsl::*;
+
+    let result = conn.transaction::<_, ServiceError, _>(|conn| {
+        let draft: Draft = posts
+            .filter(
+                id.eq(draft_id)
+                    .and(published.eq(false))
+                    .and(deleted.eq(false)),
+            )
+            .select(Draft::as_select())
+            .first(conn)?;
+
+        let parent = match draft.parent_id {
+            Some(draft_parent_id) => {
+                match find_referenced_post(draft_parent_id, draft.poster_id, conn)? {
+                    Some(_) => Some(
+                        diesel::update(posts)
+                            .filter(id.eq(draft_parent_id))
+                            .set(reply_count.eq(reply_count + 1))
+                            .returning(Post::as_returning())
+                            .get_result(conn)?,
+                    ),
+                    None => {
+                        return Err(ServiceError::BadRequest(
+                            "parent_post_not_found",
+                            message!("draft-parent-not-found", uuid = draft.uuid),
+                        ))
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let quoted = match draft.quote_of_id {
+            Some(quoted_id) => match find_referenced_post(quoted_id, draft.poster_id, conn)? {
+                Some(_) => Some(
+                    diesel::update(posts)
+                        .filter(id.eq(quoted_id))
+                        .set(quote_count.eq(quote_count + 1))
+                        .returning(Post::as_returning())
+                        .get_result(conn)?,
+                ),
+                None => {
+                    return Err(ServiceError::BadRequest(
+                        "quoted_post_not_found",
+                        message!("draft-quote-not-found", uuid = draft.uuid),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let now = Utc::now().naive_utc();
+        let post = diesel::update(posts)
+            .filter(id.eq(draft.id))
+            .set((
+                published.eq(true),
+                publish_at.eq(None::<NaiveDateTime>),
+                publish_error.eq(None::<String>),
+                created_at.eq(now),
+            ))
+            .returning(Post::as_returning())
+            .get_result(conn)?;
+
+        // polls run for the duration chosen by the poster, counted from publication
+        postpone_poll(post.id, now - draft.created_at, conn)?;
+
+        Ok((post, parent, quoted))
+    });
+
+    // the schedule is dropped too, so the scheduler doesn't retry the draft
+    if let Err(ServiceError::BadRequest(code, _)) = &result {
+        diesel::update(posts)
+            .filter(id.eq(draft_id))
+            .set((
+                publish_at.eq(None::<NaiveDateTime>),
+                publish_error.eq(Some(*code)),
+            ))
+            .execute(conn)?;
+    }
+
+    result
+}
+
+// A published post with its parent and quoted posts and its poster's username.
+type PublishedPost = (Post, Option<Post>, Option<Post>, String);
+
+fn publish_due_posts(pool: &WritePool) -> Result<Vec<PublishedPost>, ServiceError> {
+    use schema::posts::dsl::{deleted, id, posts, publish_at, published};
+    use schema::users::dsl::{username, users};
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => {
+            return Err(ServiceError::InternalServerError(
+                "database_unavailable",
+                message!("database-unavailable"),
+            ))
+        }
+    };
+
+    let due: Vec<(i32, String)> = posts
+        .inner_join(users)
+        .filter(
+            published
+                .eq(false)
+                .and(deleted.eq(false))
+                .and(publish_at.le(Utc::now().naive_utc())),
+        )
+        .select((id, username))
+        .load(&mut conn)?;
+
+    let mut published_posts = Vec::with_capacity(due.len());
+    for (due_id, poster_username) in due {
+        match publish_draft(due_id, &mut conn) {
+            Ok((post, parent, quoted)) => {
+                published_posts.push((post, parent, quoted, poster_username))
+            }
+            Err(err) => log::warn!("Failed to publish scheduled post {}: {}", due_id, err),
+        }
+    }
+
+    Ok(published_posts)
+}
+
+pub async fn run_scheduler(pool: WritePool, hub: EventHub) {
+    let mut interval = actix_web::rt::time::interval(SCHEDULER_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        match web::block(move || publish_due_posts(&pool)).await {
+            Ok(Ok(published_posts)) => {
+                for (post, parent, quoted, poster_username) in published_posts {
+                    publish_created(&hub, &post, parent, quoted, poster_username);
+                }
+            }
+            Ok(Err(err)) => log::warn!("Failed to publish scheduled posts: {}", err),
+            Err(err) => log::warn!("Failed to publish scheduled posts: {}", err),
+        }
+    }
+}
+
+#[post("/create")]
+async fn create_draft(
+    info: web::Json<DraftCreate>,
+    pool: web::Data<WritePool>,
+    app_state: web::Data<AppState>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::posts;
+
+    if let Some(poll) = &info.poll {
+        poll.validate()?;
+    }
+    validate_publish_at(&info.publish_at)?;
+    let content = validate_content(
+        &info.body,
+        &info.content_warning,
+        !info.attachments.is_empty() || info.poll.is_some(),
+        app_state.max_post_length,
+    )?;
+    let preview_pool = pool.get_ref().clone();
+
+    let draft = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Draft, diesel::result::Error, _>(|conn| {
+            let parent = find_published_post(&info.parent_uuid, current_user.id, conn)?;
+            let quoted = find_published_post(&info.quote_uuid, current_user.id, conn)?;
+
+            let draft_visibility = match (info.visibility, &parent) {
+                (Some(chosen), _) => chosen.as_str().to_string(),
+                (None, Some(parent)) => parent.visibility.clone(),
+                (None, None) => Visibility::Public.as_str().to_string(),
+            };
+
+            let new_post = NewPost {
+                uuid: generate_uid(),
+                parent_id: parent.map(|parent| parent.id),
+                poster_id: current_user.id,
+                body: &content.body,
+                repost_of_id: None,
+                quote_of_id: quoted.map(|quoted| quoted.id),
+                published: false,
+                publish_at: info.publish_at,
+                visibility: &draft_visibility,
+                content_warning: content.content_warning.as_deref(),
+                sensitive_media: info.sensitive_media,
+            };
+
+            let draft = diesel::insert_into(posts)
+                .values(&new_post)
+                .returning(Draft::as_returning())
+                .get_result(conn)?;
+
+            if let Some(poll) = &info.poll {
+                create_poll(draft.id, poll, conn)?;
+            }
+            attach_to_post(draft.id, current_user.id, &info.attachments, conn)?;
+            record_mentions(draft.id, current_user.id, &draft.body, conn)?;
+
+            Ok(draft)
+        });
+
+        match result {
+            Ok(draft) => Ok(draft),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::BadRequest(
+                "referenced_post_not_found",
+                message!("referenced-post-not-found"),
+            )),
+            // `attach_to_post` rolls back when an attachment can't be used
+            Err(diesel::result::Error::RollbackTransaction) => Err(ServiceError::BadRequest(
+                "attachments_unavailable",
+                message!("attachments-unavailable"),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    fetch_in_background(&draft.body, preview_pool, app_state.link_previews.clone());
+
+    Ok(HttpResponse::Ok().json(DraftRead::from(draft)))
+}
+
+#[get("/list")]
+async fn list_drafts(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{created_at, deleted, poster_id, posts, published};
+
+    let drafts = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match posts
+            .filter(
+                poster_id
+                    .eq(current_user.id)
+                    .and(published.eq(false))
+                    .and(deleted.eq(false)),
+            )
+            .select(Draft::as_select())
+            .offset(pagination.offset as i64)
+            .limit(pagination.limit as i64)
+            .order_by(created_at.desc())
+            .load(&mut conn)
+        {
+            Ok(drafts) => Ok(drafts),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(DraftsRead {
+        drafts: drafts.into_iter().map(DraftRead::from).collect(),
+    }))
+}
+
+#[put("/{target_draft_uuid}")]
+async fn update_draft(
+    target_draft_uuid: web::Path<String>,
+    info: web::Json<DraftUpdate>,
+    pool: web::Data<WritePool>,
+    app_state: web::Data<AppState>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::attachments::dsl::{attachments, post_id as attachment_post_id};
+    use schema::polls::dsl::{polls, post_id as poll_post_id};
+    use schema::posts::dsl::{
+        body, deleted, poster_id, posts, publish_at, publish_error, published, uuid,
+    };
+
+    validate_publish_at(&info.publish_at)?;
+    // whether an empty body is fine depends on the draft, checked below
+    let content = validate_content(&info.body, &None, true, app_state.max_post_length)?;
+    let preview_pool = pool.get_ref().clone();
+
+    let draft = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        conn.transaction::<Draft, ServiceError, _>(|conn| {
+            let draft = match diesel::update(posts)
+                .filter(
+                    uuid.eq(target_draft_uuid.as_str())
+                        .and(poster_id.eq(current_user.id))
+                        .and(published.eq(false))
+                        .and(deleted.eq(false)),
+                )
+                .set((
+                    body.eq(&content.body),
+                    publish_at.eq(info.publish_at),
+                    publish_error.eq(None::<String>),
+                ))
+                .returning(Draft::as_returning())
+                .get_result(conn)
+                .optional()?
+            {
+                Some(draft) => draft,
+                None => {
+                    return Err(ServiceError::NotFound(
+                        "draft_not_found",
+                        message!("draft-not-found", uuid = target_draft_uuid),
+                    ))
+                }
+            };
+
+            if draft.body.is_empty() {
+                let attachment_count: i64 = attachments
+                    .filter(attachment_post_id.eq(draft.id))
+                    .count()
+                    .get_result(conn)?;
+                let poll_count: i64 = polls
+                    .filter(poll_post_id.eq(draft.id))
+                    .count()
+                    .get_result(conn)?;
+                if attachment_count == 0 && poll_count == 0 {
+                    return Err(ServiceError::InvalidFields(vec![FieldError::new(
+                        "body",
+                        "empty",
+                        message!("empty-post"),
+                    )]));
+                }
+            }
+
+            record_mentions(draft.id, current_user.id, &draft.body, conn)?;
+
+            Ok(draft)
+        })
+    })
+    .await??;
+
+    fetch_in_background(&draft.body, preview_pool, app_state.link_previews.clone());
+
+    Ok(HttpResponse::Ok().json(DraftRead::from(draft)))
+}
+
+#[post("/{target_draft_uuid}/publish")]
+async fn publish_draft_now(
+    target_draft_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{deleted, id, poster_id, posts, published, uuid};
+
+    let poster_username = current_user.username.clone();
+    let (post, parent, quoted) = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let draft_id = match posts
+            .filter(
+                uuid.eq(target_draft_uuid.as_str())
+                    .and(poster_id.eq(current_user.id))
+                    .and(published.eq(false))
+                    .and(deleted.eq(false)),
+            )
+            .select(id)
+            .first::<i32>(&mut conn)
+        {
+            Ok(draft_id) => draft_id,
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::NotFound(
+                    "draft_not_found",
+                    message!("draft-not-found", uuid = target_draft_uuid),
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        publish_draft(draft_id, &mut conn)
+    })
+    .await??;
+
+    publish_created(&hub, &post, parent, quoted, poster_username);
+
+    Ok(HttpResponse::Ok().json(PublishedRead { uuid: post.uuid }))
+}
+
+#[delete("/{target_draft_uuid}")]
+async fn delete_draft(
+    target_draft_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{deleted, poster_id, posts, published, uuid};
+
+    let draft = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match diesel::update(posts)
+            .filter(
+                uuid.eq(target_draft_uuid.as_str())
+                    .and(poster_id.eq(current_user.id))
+                    .and(published.eq(false))
+                    .and(deleted.eq(false)),
+            )
+            .set(deleted.eq(true))
+            .returning(Draft::as_returning())
+            .get_result(&mut conn)
+        {
+            Ok(draft) => Ok(draft),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "draft_not_found",
+                message!("draft-not-found", uuid = target_draft_uuid),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(DraftRead::from(draft)))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/drafts")
+            .service(create_draft)
+            .service(list_drafts)
+            .service(update_draft)
+            .service(publish_draft_now)
+            .service(delete_draft),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use microblogs::test_connection;
+
+    use super::*;
+
+    // Draft 3 replies to the live post 1 and draft 4 to the deleted post 2.
+    fn seed_drafts(conn: &mut DbConn) {
+        conn.batch_execute(
+            "INSERT INTO users (id, username, email, real_name, summary, password) VALUES
+                 (1, 'a', 'a@example.com', 'A', '', 'x'),
+                 (2, 'b', 'b@example.com', 'B', '', 'x');
+             INSERT INTO posts (id, uuid, parent_id, poster_id, body, deleted) VALUES
+                 (1, 'live', NULL, 2, 'live', FALSE),
+                 (2, 'gone', NULL, 2, 'gone', TRUE);
+             INSERT INTO posts (id, uuid, parent_id, poster_id, body, published, publish_at)
+             VALUES
+                 (3, 'reply', 1, 1, 'reply', FALSE, '2000-01-01 00:00:00'),
+                 (4, 'orphan', 2, 1, 'orphan', FALSE, '2000-01-01 00:00:00');",
+        )
+        .unwrap();
+    }
+
+    fn load_draft(
+        draft_id: i32,
+        conn: &mut DbConn,
+    ) -> (bool, Option<NaiveDateTime>, Option<String>) {
+        use schema::posts::dsl::{id, posts, publish_at, publish_error, published};
+
+        posts
+            .filter(id.eq(draft_id))
+            .select((published, publish_at, publish_error))
+            .first(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn publishes_replies_to_live_posts() {
+        let mut conn = test_connection();
+        seed_drafts(&mut conn);
+
+        let (post, parent, quoted) = publish_draft(3, &mut conn).unwrap();
+        assert_eq!(post.id, 3);
+        assert_eq!(parent.map(|parent| parent.reply_count), Some(1));
+        assert!(quoted.is_none());
+        assert_eq!(load_draft(3, &mut conn), (true, None, None));
+    }
+
+    #[test]
+    fn keeps_replies_to_deleted_posts_unpublished() {
+        let mut conn = test_connection();
+        seed_drafts(&mut conn);
+
+        match publish_draft(4, &mut conn) {
+            Ok(_) => panic!("published a reply to a deleted post"),
+            Err(err) => assert_eq!(err.code(), "parent_post_not_found"),
+        }
+        assert_eq!(
+            load_draft(4, &mut conn),
+            (false, None, Some("parent_post_not_found".to_string()))
+        );
+    }
+}
@@ -1,37 +1,190 @@
 use std::fmt::Display;
 
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use diesel::result::DatabaseErrorKind;
+use serde::Serialize;
+
+use crate::{
+    i18n::{current_locale, Message, DEFAULT_LOCALE},
+    request_id,
+};
 
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: Message,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &'static str, message: Message) -> Self {
+        FieldError {
+            field: field.to_string(),
+            code,
+            message,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FieldErrorRead<'a> {
+    field: &'a str,
+    code: &'a str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorRead<'a> {
+    code: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Vec<FieldErrorRead<'a>>>,
+    request_id: Option<String>,
+}
+
+// Every variant carries a stable code, such as `post_not_found`, next to the
+// message shown to users, so clients never need to match on messages. Codes
+// are the same in every locale.
 #[derive(Debug)]
 pub enum ServiceError {
-    InternalServerError(String),
-    Unauthorized(String),
-    BadRequest(String),
-    NotFound(String),
+    InternalServerError(&'static str, Message),
+    Unauthorized(&'static str, Message),
+    Forbidden(&'static str, Message),
+    BadRequest(&'static str, Message),
+    NotFound(&'static str, Message),
+    Conflict(&'static str, Message),
+    PayloadTooLarge(&'static str, Message),
+    TooManyRequests(&'static str, Message),
+    InvalidFields(Vec<FieldError>),
+}
+
+impl ServiceError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::InternalServerError(code, _)
+            | ServiceError::Unauthorized(code, _)
+            | ServiceError::Forbidden(code, _)
+            | ServiceError::BadRequest(code, _)
+            | ServiceError::NotFound(code, _)
+            | ServiceError::Conflict(code, _)
+            | ServiceError::PayloadTooLarge(code, _)
+            | ServiceError::TooManyRequests(code, _) => code,
+            ServiceError::InvalidFields(_) => "invalid_fields",
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        match self {
+            ServiceError::InternalServerError(_, msg)
+            | ServiceError::Unauthorized(_, msg)
+            | ServiceError::Forbidden(_, msg)
+            | ServiceError::BadRequest(_, msg)
+            | ServiceError::NotFound(_, msg)
+            | ServiceError::Conflict(_, msg)
+            | ServiceError::PayloadTooLarge(_, msg)
+            | ServiceError::TooManyRequests(_, msg) => msg.clone(),
+            ServiceError::InvalidFields(_) => crate::message!("invalid-fields"),
+        }
+    }
 }
 
+// The fallback for database errors a handler doesn't map to something more
+// specific: missing rows are not found, unique violations are conflicts, other
+// broken constraints are bad requests and anything else is an internal error.
+impl From<diesel::result::Error> for ServiceError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => {
+                ServiceError::NotFound("not_found", crate::message!("resource-not-found"))
+            }
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ServiceError::Conflict("conflict", crate::message!("resource-conflict"))
+            }
+            diesel::result::Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation
+                | DatabaseErrorKind::CheckViolation
+                | DatabaseErrorKind::NotNullViolation,
+                _,
+            ) => ServiceError::BadRequest(
+                "constraint_violation",
+                crate::message!("constraint-violation"),
+            ),
+            err => {
+                log::error!("Database error: {}", err);
+                ServiceError::InternalServerError(
+                    "internal_error",
+                    crate::message!("internal-error"),
+                )
+            }
+        }
+    }
+}
+
+// Used for logging, so it always renders in the default locale.
 impl Display for ServiceError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ServiceError::InternalServerError(msg) => write!(f, "Erro interno: {}", msg),
-            ServiceError::Unauthorized(msg) => write!(f, "Não autorizado: {}", msg),
-            ServiceError::BadRequest(msg) => write!(f, "Requisição inválida: {}", msg),
-            ServiceError::NotFound(msg) => write!(f, "Não encontrado: {}", msg),
+            ServiceError::InvalidFields(errors) => write!(
+                f,
+                "{}: {}",
+                self.code(),
+                errors
+                    .iter()
+                    .map(|error| format!(
+                        "{}: {}",
+                        error.field,
+                        error.message.render(DEFAULT_LOCALE)
+                    ))
+                    .collect::<Vec<String>>()
+                    .join("; ")
+            ),
+            _ => write!(
+                f,
+                "{}: {}",
+                self.code(),
+                self.message().render(DEFAULT_LOCALE)
+            ),
         }
     }
 }
 
 impl ResponseError for ServiceError {
-    fn error_response(&self) -> HttpResponse {
+    fn status_code(&self) -> StatusCode {
         match self {
-            ServiceError::InternalServerError(msg) => {
-                HttpResponse::InternalServerError().body(format!("{}", msg))
-            }
-            ServiceError::Unauthorized(msg) => {
-                HttpResponse::Unauthorized().body(format!("{}", msg))
+            ServiceError::InternalServerError(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServiceError::Unauthorized(..) => StatusCode::UNAUTHORIZED,
+            ServiceError::Forbidden(..) => StatusCode::FORBIDDEN,
+            ServiceError::BadRequest(..) | ServiceError::InvalidFields(_) => {
+                StatusCode::BAD_REQUEST
             }
-            ServiceError::BadRequest(msg) => HttpResponse::BadRequest().body(format!("{}", msg)),
-            ServiceError::NotFound(msg) => HttpResponse::NotFound().body(format!("{}", msg)),
+            ServiceError::NotFound(..) => StatusCode::NOT_FOUND,
+            ServiceError::Conflict(..) => StatusCode::CONFLICT,
+            ServiceError::PayloadTooLarge(..) => StatusCode::PAYLOAD_TOO_LARGE,
+            ServiceError::TooManyRequests(..) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let locale = current_locale();
+        let details = match self {
+            ServiceError::InvalidFields(errors) => Some(
+                errors
+                    .iter()
+                    .map(|error| FieldErrorRead {
+                        field: &error.field,
+                        code: error.code,
+                        message: error.message.render(locale),
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        HttpResponse::build(self.status_code()).json(ErrorRead {
+            code: self.code(),
+            message: self.message().render(locale),
+            details,
+            request_id: request_id::current(),
+        })
+    }
 }
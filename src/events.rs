@@ -0,0 +1,87 @@
+use std::convert::Infallible;
+
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective},
+    web::{self, Bytes, ServiceConfig},
+    Error, HttpResponse,
+};
+use microblogs::{
+    errors::ServiceError,
+    hub::{Event, EventHub, Subscriber},
+    message, DbPool,
+};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+use crate::{relationships::load_subscriber, users::UserDetails};
+
+async fn fetch_subscriber(pool: web::Data<DbPool>, user_id: i32) -> Result<Subscriber, Error> {
+    Ok(web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        load_subscriber(user_id, &mut conn).map_err(ServiceError::from)
+    })
+    .await??)
+}
+
+#[get("/stream")]
+async fn stream_events(
+    hub: web::Data<EventHub>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    let user_id = current_user.id;
+    let receiver = hub.subscribe();
+    let subscriber = fetch_subscriber(pool.clone(), user_id).await?;
+
+    // lagged receivers just skip the events they missed
+    let stream = BroadcastStream::new(receiver)
+        .then(move |event| {
+            let pool = pool.clone();
+            async move {
+                // follows, blocks and mutes may have changed since the stream opened
+                let subscriber = match event {
+                    Ok(Event::NewPost { .. }) => Some(fetch_subscriber(pool, user_id).await),
+                    _ => None,
+                };
+                (event, subscriber)
+            }
+        })
+        .filter_map(move |(event, fresh)| {
+            let subscriber = match &fresh {
+                Some(Ok(fresh)) => fresh,
+                // without the subscriber's relationships the post can't be checked
+                Some(Err(_)) => return None,
+                None => &subscriber,
+            };
+            let event = match event {
+                Ok(event) if event.is_visible_to(subscriber) => event,
+                _ => return None,
+            };
+
+            match serde_json::to_string(&event) {
+                Ok(payload) => Some(Ok::<_, Infallible>(Bytes::from(format!(
+                    "data: {}\n\n",
+                    payload
+                )))),
+                Err(_) => None,
+            }
+        });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(stream))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(web::scope("/events").service(stream_events));
+}
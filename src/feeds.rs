@@ -1,62 +1,434 @@
+use std::collections::{HashMap, HashSet};
+
 use actix_web::{
     get,
     web::{self, ServiceConfig},
     HttpResponse,
 };
 use diesel::{
-    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, Queryable, RunQueryDsl,
-    Selectable, SelectableHelper,
+    dsl::count_star,
+    sql_types::{Bool, Integer, Nullable, Text},
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, Queryable, QueryableByName,
+    RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{
+    errors::ServiceError,
+    markup::{self, Facet},
+    message, schema, DbConn, DbPool, Pagination, LIKE_REACTION,
 };
-use microblogs::{errors::ServiceError, schema, DbPool, Pagination};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    posts::{Like, Post},
-    users::UserDetails,
+    bookmarks::BookmarkCollection,
+    filters::{load_filters, Filter},
+    link_previews::{first_link, load_link_previews, LinkPreviewRead},
+    polls::{load_polls, PollRead},
+    posts::{Bookmark, Like, Post},
+    users::{load_preferences, SensitiveMedia, UserDetails},
+    visibility::{listed_for, visible_to, visible_to_sql},
 };
 
+const DEFAULT_THREAD_DEPTH: i32 = 6;
+const MAX_THREAD_DEPTH: i32 = 16;
+const DEFAULT_BRANCH_LIMIT: i32 = 10;
+const MAX_BRANCH_LIMIT: i32 = 50;
+
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = schema::users)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(microblogs::DbBackend))]
 pub struct Poster {
     pub username: String,
     pub real_name: String,
 }
 
-#[derive(Serialize)]
+pub type PostRow = (Post, Poster, Option<Like>, Option<Bookmark>);
+
+#[derive(Serialize, Clone)]
 pub struct PosterRead {
     username: String,
     real_name: String,
 }
 
-#[derive(Serialize)]
+impl From<Poster> for PosterRead {
+    fn from(poster: Poster) -> Self {
+        Self {
+            username: poster.username,
+            real_name: poster.real_name,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReactionRead {
+    emoji: String,
+    count: i64,
+    reacted_by_user: bool,
+}
+
+#[derive(Serialize, Clone)]
 pub struct PostRead {
     uuid: String,
     body: String,
+    html: String,
+    facets: Vec<Facet>,
     created_at: String,
+    visibility: String,
+    content_warning: Option<String>,
+    sensitive_media: bool,
+    media_blurred: bool,
+    pinned: bool,
     reply_count: i32,
     like_count: i32,
+    repost_count: i32,
+    quote_count: i32,
     liked_by_user: bool,
+    reposted_by_user: bool,
+    bookmarked_by_user: bool,
+    reactions: Vec<ReactionRead>,
+    poll: Option<PollRead>,
+    link_preview: Option<LinkPreviewRead>,
+    filtered: Option<String>,
     poster: PosterRead,
+    reposted_by: Option<PosterRead>,
+    quoted_post: Option<Box<PostRead>>,
 }
 
-impl From<(Post, Poster, Option<Like>)> for PostRead {
-    fn from((post, poster, like): (Post, Poster, Option<Like>)) -> Self {
+impl From<PostRow> for PostRead {
+    fn from((post, poster, like, bookmark): PostRow) -> Self {
+        let rendered = markup::render(&post.body);
         Self {
             uuid: post.uuid,
             body: post.body,
+            html: rendered.html,
+            facets: rendered.facets,
             created_at: post.created_at.to_string(),
+            visibility: post.visibility,
+            content_warning: post.content_warning,
+            sensitive_media: post.sensitive_media,
+            media_blurred: false,
+            pinned: false,
             reply_count: post.reply_count,
             like_count: post.like_count,
+            repost_count: post.repost_count,
+            quote_count: post.quote_count,
             liked_by_user: like.is_some(),
-            poster: PosterRead {
-                username: poster.username,
-                real_name: poster.real_name,
-            },
+            reposted_by_user: false,
+            bookmarked_by_user: bookmark.is_some(),
+            reactions: Vec::new(),
+            poll: None,
+            link_preview: None,
+            filtered: None,
+            poster: PosterRead::from(poster),
+            reposted_by: None,
+            quoted_post: None,
         }
     }
 }
 
+impl PostRead {
+    // Only profiles show which posts are pinned.
+    pub fn mark_pinned(&mut self) {
+        self.pinned = true;
+    }
+}
+
+pub fn load_posts_by_id(
+    ids: &[i32],
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<HashMap<i32, PostRow>, diesel::result::Error> {
+    use schema::bookmarks::dsl::{
+        bookmarks, deleted as bookmark_deleted, post_id as bookmark_post_id,
+        user_id as bookmark_user_id,
+    };
+    use schema::likes::dsl::{
+        deleted as like_deleted, emoji as like_emoji, likes, post_id as like_post_id,
+        user_id as like_user_id,
+    };
+    use schema::posts::dsl::{
+        deleted as post_deleted, id as post_id, posts, published as post_published,
+    };
+    use schema::users::dsl::users;
+
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let rows = posts
+        .inner_join(users)
+        .left_join(
+            likes.on(like_post_id
+                .eq(post_id)
+                .and(like_user_id.eq(current_user_id))
+                .and(like_emoji.eq(LIKE_REACTION))
+                .and(like_deleted.eq(false))),
+        )
+        .left_join(
+            bookmarks.on(bookmark_post_id
+                .eq(post_id)
+                .and(bookmark_user_id.eq(current_user_id))
+                .and(bookmark_deleted.eq(false))),
+        )
+        .filter(
+            post_deleted
+                .eq(false)
+                .and(post_published.eq(true))
+                .and(post_id.eq_any(ids))
+                .and(visible_to(current_user_id)),
+        )
+        .select((
+            Post::as_select(),
+            Poster::as_select(),
+            Option::<Like>::as_select(),
+            Option::<Bookmark>::as_select(),
+        ))
+        .load::<PostRow>(conn)?;
+
+    Ok(rows.into_iter().map(|row| (row.0.id, row)).collect())
+}
+
+// Turns loaded rows into `PostRead`s, replacing reposts with the post they share
+// and embedding quoted posts.
+pub fn load_post_reads(
+    rows: Vec<PostRow>,
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Vec<PostRead>, diesel::result::Error> {
+    use schema::posts::dsl::{deleted, poster_id, posts, repost_of_id};
+
+    let mut referenced_ids: Vec<i32> = rows
+        .iter()
+        .filter_map(|(post, _, _, _)| post.repost_of_id.or(post.quote_of_id))
+        .collect();
+    let mut referenced = load_posts_by_id(&referenced_ids, current_user_id, conn)?;
+
+    // a reposted quote also embeds the post it quotes
+    let nested_ids: Vec<i32> = referenced
+        .values()
+        .filter_map(|(post, _, _, _)| post.quote_of_id)
+        .filter(|quoted_id| !referenced.contains_key(quoted_id))
+        .collect();
+    referenced.extend(load_posts_by_id(&nested_ids, current_user_id, conn)?);
+    referenced_ids.extend(nested_ids);
+
+    let mut shown_ids: Vec<i32> = rows.iter().map(|(post, _, _, _)| post.id).collect();
+    shown_ids.extend(referenced_ids);
+    let reposted: HashSet<i32> = posts
+        .filter(
+            poster_id
+                .eq(current_user_id)
+                .and(deleted.eq(false))
+                .and(repost_of_id.eq_any(&shown_ids)),
+        )
+        .select(repost_of_id)
+        .load::<Option<i32>>(conn)?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let mut reactions = load_reactions(&shown_ids, current_user_id, conn)?;
+    let mut polls = load_polls(&shown_ids, current_user_id, conn)?;
+
+    let quotes: Vec<(i32, i32)> = referenced
+        .values()
+        .filter_map(|(post, _, _, _)| post.quote_of_id.map(|quoted_id| (post.id, quoted_id)))
+        .collect();
+    let mut referenced: HashMap<i32, PostRead> = referenced
+        .into_iter()
+        .map(|(id, row)| {
+            let mut read = PostRead::from(row);
+            read.reposted_by_user = reposted.contains(&id);
+            read.reactions = reactions.get(&id).cloned().unwrap_or_default();
+            read.poll = polls.get(&id).cloned();
+            (id, read)
+        })
+        .collect();
+    for (id, quoted_id) in quotes {
+        let quoted = embedded(&referenced, quoted_id);
+        if let Some(read) = referenced.get_mut(&id) {
+            read.quoted_post = quoted;
+        }
+    }
+
+    let mut reads = Vec::with_capacity(rows.len());
+    for (post, poster, like, bookmark) in rows {
+        match post.repost_of_id {
+            Some(original_id) => {
+                // reposts of deleted posts are skipped
+                if let Some(original) = referenced.get(&original_id) {
+                    let mut read = original.clone();
+                    read.reposted_by = Some(PosterRead::from(poster));
+                    reads.push(read);
+                }
+            }
+            None => {
+                let quoted = post
+                    .quote_of_id
+                    .and_then(|quoted_id| embedded(&referenced, quoted_id));
+                let id = post.id;
+                let mut read = PostRead::from((post, poster, like, bookmark));
+                read.reposted_by_user = reposted.contains(&id);
+                read.reactions = reactions.remove(&id).unwrap_or_default();
+                read.poll = polls.remove(&id);
+                read.quoted_post = quoted;
+                reads.push(read);
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+    for read in &reads {
+        collect_links(read, &mut links);
+    }
+    let previews = load_link_previews(&links, conn)?;
+    for read in reads.iter_mut() {
+        attach_link_preview(read, &previews);
+    }
+
+    Ok(reads)
+}
+
+fn collect_links(read: &PostRead, links: &mut Vec<String>) {
+    if let Some(link) = first_link(&read.facets) {
+        links.push(link.to_string());
+    }
+    if let Some(quoted) = &read.quoted_post {
+        collect_links(quoted, links);
+    }
+}
+
+fn attach_link_preview(read: &mut PostRead, previews: &HashMap<String, LinkPreviewRead>) {
+    read.link_preview = first_link(&read.facets).and_then(|link| previews.get(link).cloned());
+    if let Some(quoted) = read.quoted_post.as_mut() {
+        attach_link_preview(quoted, previews);
+    }
+}
+
+fn load_reactions(
+    ids: &[i32],
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<HashMap<i32, Vec<ReactionRead>>, diesel::result::Error> {
+    use schema::likes::dsl::{deleted, emoji, likes, post_id, user_id};
+
+    let counts: Vec<(i32, String, i64)> = likes
+        .filter(deleted.eq(false).and(post_id.eq_any(ids)))
+        .group_by((post_id, emoji))
+        .select((post_id, emoji, count_star()))
+        .load(conn)?;
+    let own: HashSet<(i32, String)> = likes
+        .filter(
+            deleted
+                .eq(false)
+                .and(user_id.eq(current_user_id))
+                .and(post_id.eq_any(ids)),
+        )
+        .select((post_id, emoji))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    let mut reactions: HashMap<i32, Vec<ReactionRead>> = HashMap::new();
+    for (reacted_post_id, reaction, count) in counts {
+        let reacted_by_user = own.contains(&(reacted_post_id, reaction.clone()));
+        reactions
+            .entry(reacted_post_id)
+            .or_default()
+            .push(ReactionRead {
+                emoji: reaction,
+                count,
+                reacted_by_user,
+            });
+    }
+    for post_reactions in reactions.values_mut() {
+        post_reactions.sort_by(|a, b| b.count.cmp(&a.count).then(a.emoji.cmp(&b.emoji)));
+    }
+
+    Ok(reactions)
+}
+
+// Drops the posts matching a hiding filter and marks the ones matching a
+// warning filter with the filtered phrase, so the webapp can collapse them.
+fn apply_filters(reads: Vec<PostRead>, filters: &[Filter]) -> Vec<PostRead> {
+    if filters.is_empty() {
+        return reads;
+    }
+
+    reads
+        .into_iter()
+        .filter_map(|mut read| {
+            let matched: Vec<&Filter> = filters
+                .iter()
+                .filter(|filter| {
+                    filter.matches(&read.body)
+                        || read
+                            .content_warning
+                            .as_ref()
+                            .is_some_and(|content_warning| filter.matches(content_warning))
+                        || read
+                            .quoted_post
+                            .as_ref()
+                            .is_some_and(|quoted| filter.matches(&quoted.body))
+                })
+                .collect();
+
+            if matched.iter().any(|filter| filter.hides()) {
+                return None;
+            }
+            read.filtered = matched.first().map(|filter| filter.phrase.clone());
+            Some(read)
+        })
+        .collect()
+}
+
+// Applies the viewer's sensitive media preference, dropping or blurring posts
+// (and quoted posts) flagged as sensitive.
+fn apply_media_preference(reads: Vec<PostRead>, sensitive_media: &str) -> Vec<PostRead> {
+    reads
+        .into_iter()
+        .filter(|read| {
+            sensitive_media != SensitiveMedia::Hide.as_str()
+                || !(read.sensitive_media
+                    || read
+                        .quoted_post
+                        .as_ref()
+                        .is_some_and(|quoted| quoted.sensitive_media))
+        })
+        .map(|mut read| {
+            let blur = sensitive_media == SensitiveMedia::Blur.as_str();
+            read.media_blurred = blur && read.sensitive_media;
+            if let Some(quoted) = read.quoted_post.as_mut() {
+                quoted.media_blurred = blur && quoted.sensitive_media;
+            }
+            read
+        })
+        .collect()
+}
+
+// Applies the viewer's keyword filters and sensitive media preference to posts
+// shown in feeds.
+pub fn apply_preferences(
+    reads: Vec<PostRead>,
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Vec<PostRead>, diesel::result::Error> {
+    let filters = load_filters(current_user_id, conn)?;
+    let preferences = load_preferences(current_user_id, conn)?;
+
+    Ok(apply_media_preference(
+        apply_filters(reads, &filters),
+        &preferences.sensitive_media,
+    ))
+}
+
+fn embedded(reads: &HashMap<i32, PostRead>, id: i32) -> Option<Box<PostRead>> {
+    reads.get(&id).map(|read| {
+        let mut read = read.clone();
+        read.quoted_post = None;
+        Box::new(read)
+    })
+}
+
 #[derive(Serialize)]
 struct FeedRead {
     posts: Vec<PostRead>,
@@ -67,18 +439,166 @@ struct RepliesRead {
     replies: Vec<PostRead>,
 }
 
+#[derive(Serialize)]
+struct LikerRead {
+    poster: PosterRead,
+    liked_at: String,
+}
+
+#[derive(Serialize)]
+struct LikersRead {
+    likes: Vec<LikerRead>,
+}
+
+#[derive(Deserialize)]
+struct BookmarksQuery {
+    collection: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ThreadQuery {
+    depth: Option<i32>,
+    branch_limit: Option<i32>,
+}
+
+#[derive(QueryableByName)]
+struct ThreadNode {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+    #[diesel(sql_type = Nullable<Integer>)]
+    parent_id: Option<i32>,
+    #[diesel(sql_type = Integer)]
+    depth: i32,
+    #[diesel(sql_type = Bool)]
+    visible: bool,
+}
+
+// Replies that can't be shown, because they were deleted, aren't visible to the
+// viewer or were filtered out, are kept as placeholders without a post when
+// there are replies under them.
+#[derive(Serialize)]
+struct ThreadPostRead {
+    #[serde(flatten)]
+    post: Option<PostRead>,
+    unavailable: bool,
+    depth: i32,
+    replies: Vec<ThreadPostRead>,
+}
+
+#[derive(Serialize)]
+struct ThreadRead {
+    ancestors: Vec<PostRead>,
+    post: ThreadPostRead,
+}
+
+// Ancestors get negative depths, the requested post depth 0 and its replies
+// positive ones. The thread is walked by `parent_id` before anything else, so
+// only its own posts are numbered; replies are then limited per parent, oldest
+// first. Posts the viewer can't see are returned with `visible` unset, to keep
+// the replies under them. Parameters are numbered (`$1`), which both SQLite and
+// PostgreSQL understand.
+fn load_thread_nodes(
+    target_post_uuid: &str,
+    max_depth: i32,
+    branch_limit: i32,
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Vec<ThreadNode>, diesel::result::Error> {
+    let visible = format!(
+        "(posts.deleted = FALSE AND posts.published = TRUE AND {})",
+        visible_to_sql(current_user_id)
+    );
+    diesel::sql_query(format!(
+        "WITH RECURSIVE
+         ancestors(id, parent_id, depth) AS (
+             SELECT id, parent_id, 0 FROM posts
+             WHERE uuid = $1 AND repost_of_id IS NULL
+             UNION ALL
+             SELECT posts.id, posts.parent_id, ancestors.depth - 1
+             FROM posts JOIN ancestors ON posts.id = ancestors.parent_id
+         ),
+         descendants(id, depth) AS (
+             SELECT id, 0 FROM ancestors WHERE depth = 0
+             UNION ALL
+             SELECT posts.id, descendants.depth + 1
+             FROM posts JOIN descendants ON posts.parent_id = descendants.id
+             WHERE descendants.depth < $2 AND posts.published = TRUE
+                 AND posts.repost_of_id IS NULL
+         ),
+         numbered AS (
+             SELECT posts.id, posts.parent_id, descendants.depth, posts.created_at,
+                 ROW_NUMBER() OVER (PARTITION BY posts.parent_id ORDER BY posts.created_at) AS position,
+                 {visible} AS visible
+             FROM descendants JOIN posts ON posts.id = descendants.id
+         ),
+         branches(id) AS (
+             SELECT id FROM numbered WHERE depth = 0
+             UNION ALL
+             SELECT numbered.id FROM numbered JOIN branches ON numbered.parent_id = branches.id
+             WHERE numbered.position <= $3
+         )
+         SELECT id, parent_id, depth, visible FROM (
+             SELECT posts.id, posts.parent_id, ancestors.depth, posts.created_at, {visible} AS visible
+             FROM ancestors JOIN posts ON posts.id = ancestors.id
+             WHERE ancestors.depth < 0
+             UNION ALL
+             SELECT numbered.id, numbered.parent_id, numbered.depth, numbered.created_at, numbered.visible
+             FROM numbered JOIN branches ON branches.id = numbered.id
+         ) AS thread_nodes
+         ORDER BY depth, created_at"
+    ))
+    .bind::<Text, _>(target_post_uuid)
+    .bind::<Integer, _>(max_depth)
+    .bind::<Integer, _>(branch_limit)
+    .load(conn)
+}
+
+fn build_thread(
+    id: i32,
+    depth: i32,
+    reads: &mut HashMap<i32, PostRead>,
+    children: &HashMap<i32, Vec<i32>>,
+) -> Option<ThreadPostRead> {
+    let post = reads.remove(&id);
+    let replies: Vec<ThreadPostRead> = children
+        .get(&id)
+        .map(|child_ids| {
+            child_ids
+                .iter()
+                .filter_map(|child_id| build_thread(*child_id, depth + 1, reads, children))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if post.is_none() && replies.is_empty() {
+        return None;
+    }
+
+    Some(ThreadPostRead {
+        unavailable: post.is_none(),
+        post,
+        depth,
+        replies,
+    })
+}
+
 #[get("/list")]
 async fn get_feed(
     pagination: web::Query<Pagination>,
     pool: web::Data<DbPool>,
     current_user: UserDetails,
 ) -> Result<HttpResponse, actix_web::Error> {
+    use schema::bookmarks::dsl::{
+        bookmarks, deleted as bookmark_deleted, post_id as bookmark_post_id,
+        user_id as bookmark_user_id,
+    };
     use schema::likes::dsl::{
-        deleted as like_deleted, likes, post_id as like_post_id, user_id as like_user_id,
+        deleted as like_deleted, emoji as like_emoji, likes, post_id as like_post_id,
+        user_id as like_user_id,
     };
     use schema::posts::dsl::{
         created_at as post_created_at, deleted as post_deleted, id as post_id,
-        parent_id as post_parent_id, posts,
+        parent_id as post_parent_id, posts, published as post_published,
     };
     use schema::users::dsl::users;
 
@@ -86,9 +606,10 @@ async fn get_feed(
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
@@ -98,34 +619,44 @@ async fn get_feed(
                 likes.on(like_post_id
                     .eq(post_id)
                     .and(like_user_id.eq(current_user.id))
+                    .and(like_emoji.eq(LIKE_REACTION))
                     .and(like_deleted.eq(false))),
             )
-            .filter(post_deleted.eq(false).and(post_parent_id.is_null()))
+            .left_join(
+                bookmarks.on(bookmark_post_id
+                    .eq(post_id)
+                    .and(bookmark_user_id.eq(current_user.id))
+                    .and(bookmark_deleted.eq(false))),
+            )
+            .filter(
+                post_deleted
+                    .eq(false)
+                    .and(post_published.eq(true))
+                    .and(post_parent_id.is_null())
+                    .and(listed_for(current_user.id)),
+            )
             .select((
                 Post::as_select(),
                 Poster::as_select(),
                 Option::<Like>::as_select(),
+                Option::<Bookmark>::as_select(),
             ))
             .offset(pagination.offset as i64)
             .limit(pagination.limit as i64)
             .order_by(post_created_at.desc())
-            .load::<(Post, Poster, Option<Like>)>(&mut conn)
-        {
+            .load::<PostRow>(&mut conn)
+            .and_then(|returned_posts| load_post_reads(returned_posts, current_user.id, &mut conn))
+            .and_then(|returned_posts| {
+                apply_preferences(returned_posts, current_user.id, &mut conn)
+            }) {
             Ok(returned_posts) => Ok(returned_posts),
-            Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível carregar as postagens."
-                )))
-            }
+            Err(err) => Err(err.into()),
         }
     })
     .await??;
 
     Ok(HttpResponse::Ok().json(FeedRead {
-        posts: returned_posts
-            .into_iter()
-            .map(|(post, poster, like)| PostRead::from((post, poster, like)))
-            .collect(),
+        posts: returned_posts,
     }))
 }
 
@@ -135,19 +666,28 @@ async fn get_post_details(
     pool: web::Data<DbPool>,
     current_user: UserDetails,
 ) -> Result<HttpResponse, actix_web::Error> {
+    use schema::bookmarks::dsl::{
+        bookmarks, deleted as bookmark_deleted, post_id as bookmark_post_id,
+        user_id as bookmark_user_id,
+    };
     use schema::likes::dsl::{
-        deleted as like_deleted, likes, post_id as like_post_id, user_id as like_user_id,
+        deleted as like_deleted, emoji as like_emoji, likes, post_id as like_post_id,
+        user_id as like_user_id,
+    };
+    use schema::posts::dsl::{
+        deleted as post_deleted, id as post_id, posts, published as post_published,
+        uuid as post_uuid,
     };
-    use schema::posts::dsl::{deleted as post_deleted, id as post_id, posts, uuid as post_uuid};
     use schema::users::dsl::users;
 
-    let ((post, poster), like) = web::block(move || {
+    let post = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
@@ -157,32 +697,54 @@ async fn get_post_details(
                 likes.on(like_post_id
                     .eq(post_id)
                     .and(like_user_id.eq(current_user.id))
+                    .and(like_emoji.eq(LIKE_REACTION))
                     .and(like_deleted.eq(false))),
             )
+            .left_join(
+                bookmarks.on(bookmark_post_id
+                    .eq(post_id)
+                    .and(bookmark_user_id.eq(current_user.id))
+                    .and(bookmark_deleted.eq(false))),
+            )
             .filter(
                 post_deleted
                     .eq(false)
-                    .and(post_uuid.eq(target_post_uuid.as_str())),
+                    .and(post_published.eq(true))
+                    .and(post_uuid.eq(target_post_uuid.as_str()))
+                    .and(visible_to(current_user.id)),
             )
             .select((
                 Post::as_select(),
                 Poster::as_select(),
                 Option::<Like>::as_select(),
+                Option::<Bookmark>::as_select(),
             ))
-            .first(&mut conn)
+            .first::<PostRow>(&mut conn)
+            .and_then(|row| load_post_reads(vec![row], current_user.id, &mut conn))
+            .and_then(|reads| {
+                // a post opened on its own is blurred rather than hidden
+                let preferences = load_preferences(current_user.id, &mut conn)?;
+                let sensitive_media =
+                    if preferences.sensitive_media == SensitiveMedia::Hide.as_str() {
+                        SensitiveMedia::Blur.as_str()
+                    } else {
+                        preferences.sensitive_media.as_str()
+                    };
+                Ok(apply_media_preference(reads, sensitive_media))
+            })
+            .map(|reads| reads.into_iter().next())
         {
-            Ok((post, poster, like)) => Ok(((post, poster), like)),
-            Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível carregar a postagem {}.",
-                    target_post_uuid
-                )))
-            }
+            Ok(Some(post)) => Ok(post),
+            Ok(None) | Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "post_not_found",
+                message!("post-not-found", uuid = target_post_uuid),
+            )),
+            Err(err) => Err(err.into()),
         }
     })
     .await??;
 
-    Ok(HttpResponse::Ok().json(PostRead::from((post, poster, like))))
+    Ok(HttpResponse::Ok().json(post))
 }
 
 #[get("/replies/{target_post_uuid}")]
@@ -192,11 +754,17 @@ async fn get_replies(
     pool: web::Data<DbPool>,
     current_user: UserDetails,
 ) -> Result<HttpResponse, actix_web::Error> {
+    use schema::bookmarks::dsl::{
+        bookmarks, deleted as bookmark_deleted, post_id as bookmark_post_id,
+        user_id as bookmark_user_id,
+    };
     use schema::likes::dsl::{
-        deleted as like_deleted, likes, post_id as like_post_id, user_id as like_user_id,
+        deleted as like_deleted, emoji as like_emoji, likes, post_id as like_post_id,
+        user_id as like_user_id,
     };
     use schema::posts::dsl::{
-        created_at, deleted as post_deleted, id as post_id, parent_id, posts, uuid as post_uuid,
+        created_at, deleted as post_deleted, id as post_id, parent_id, posts,
+        published as post_published, uuid as post_uuid,
     };
     use schema::users::dsl::users;
 
@@ -204,9 +772,10 @@ async fn get_replies(
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
@@ -214,18 +783,21 @@ async fn get_replies(
             .filter(
                 post_uuid
                     .eq(target_post_uuid.as_str())
-                    .and(post_deleted.eq(false)),
+                    .and(post_deleted.eq(false))
+                    .and(post_published.eq(true))
+                    .and(visible_to(current_user.id)),
             )
             .select(Post::as_select())
             .first(&mut conn)
         {
             Ok(post) => post.id,
-            Err(_) => {
-                return Err(ServiceError::NotFound(format!(
-                    "Postagem \"{}\" não encontrada.",
-                    target_post_uuid
-                )))
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::NotFound(
+                    "post_not_found",
+                    message!("post-not-found", uuid = target_post_uuid),
+                ))
             }
+            Err(err) => return Err(err.into()),
         };
 
         match posts
@@ -234,43 +806,423 @@ async fn get_replies(
                 likes.on(like_post_id
                     .eq(post_id)
                     .and(like_user_id.eq(current_user.id))
+                    .and(like_emoji.eq(LIKE_REACTION))
                     .and(like_deleted.eq(false))),
             )
-            .filter(post_deleted.eq(false).and(parent_id.eq(target_parent_id)))
+            .left_join(
+                bookmarks.on(bookmark_post_id
+                    .eq(post_id)
+                    .and(bookmark_user_id.eq(current_user.id))
+                    .and(bookmark_deleted.eq(false))),
+            )
+            .filter(
+                post_deleted
+                    .eq(false)
+                    .and(post_published.eq(true))
+                    .and(parent_id.eq(target_parent_id))
+                    .and(visible_to(current_user.id)),
+            )
             .select((
                 Post::as_select(),
                 Poster::as_select(),
                 Option::<Like>::as_select(),
+                Option::<Bookmark>::as_select(),
             ))
             .offset(pagination.offset as i64)
             .limit(pagination.limit as i64)
             .order_by(created_at.asc())
-            .load::<(Post, Poster, Option<Like>)>(&mut conn)
-        {
+            .load::<PostRow>(&mut conn)
+            .and_then(|returned_posts| load_post_reads(returned_posts, current_user.id, &mut conn))
+            .and_then(|returned_posts| {
+                apply_preferences(returned_posts, current_user.id, &mut conn)
+            }) {
             Ok(returned_posts) => Ok(returned_posts),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RepliesRead {
+        replies: returned_posts,
+    }))
+}
+
+#[get("/thread/{target_post_uuid}")]
+async fn get_thread(
+    target_post_uuid: web::Path<String>,
+    thread_query: web::Query<ThreadQuery>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, actix_web::Error> {
+    let max_depth = thread_query
+        .depth
+        .unwrap_or(DEFAULT_THREAD_DEPTH)
+        .clamp(0, MAX_THREAD_DEPTH);
+    let branch_limit = thread_query
+        .branch_limit
+        .unwrap_or(DEFAULT_BRANCH_LIMIT)
+        .clamp(1, MAX_BRANCH_LIMIT);
+
+    let thread = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível carregar as respostas da postagem {}.",
-                    target_post_uuid
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let nodes = match load_thread_nodes(
+            &target_post_uuid,
+            max_depth,
+            branch_limit,
+            current_user.id,
+            &mut conn,
+        ) {
+            Ok(nodes) => nodes,
+            Err(err) => return Err(err.into()),
+        };
+
+        let target_id = match nodes.iter().find(|node| node.depth == 0 && node.visible) {
+            Some(node) => node.id,
+            None => {
+                return Err(ServiceError::NotFound(
+                    "post_not_found",
+                    message!("post-not-found", uuid = target_post_uuid),
+                ))
             }
+        };
+
+        let ids: Vec<i32> = nodes
+            .iter()
+            .filter(|node| node.visible)
+            .map(|node| node.id)
+            .collect();
+        let reads = load_posts_by_id(&ids, current_user.id, &mut conn).and_then(|mut loaded| {
+            let rows: Vec<PostRow> = ids.iter().filter_map(|id| loaded.remove(id)).collect();
+            let row_ids: HashMap<String, i32> = rows
+                .iter()
+                .map(|(post, _, _, _)| (post.uuid.clone(), post.id))
+                .collect();
+            let reads = load_post_reads(rows, current_user.id, &mut conn)?;
+
+            Ok(apply_preferences(reads, current_user.id, &mut conn)?
+                .into_iter()
+                .filter_map(|read| row_ids.get(&read.uuid).map(|id| (*id, read)))
+                .collect::<HashMap<i32, PostRead>>())
+        });
+        let mut reads = match reads {
+            Ok(reads) => reads,
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+        for node in nodes.iter().filter(|node| node.depth > 0) {
+            if let Some(parent_id) = node.parent_id {
+                children.entry(parent_id).or_default().push(node.id);
+            }
+        }
+
+        // nodes come ordered by depth, so ancestors start from the root
+        let ancestors: Vec<PostRead> = nodes
+            .iter()
+            .filter(|node| node.depth < 0)
+            .filter_map(|node| reads.remove(&node.id))
+            .collect();
+
+        match build_thread(target_id, 0, &mut reads, &children) {
+            Some(post) if !post.unavailable => Ok(ThreadRead { ancestors, post }),
+            _ => Err(ServiceError::NotFound(
+                "post_not_found",
+                message!("post-not-found", uuid = target_post_uuid),
+            )),
         }
     })
     .await??;
 
-    Ok(HttpResponse::Ok().json(RepliesRead {
-        replies: returned_posts
+    Ok(HttpResponse::Ok().json(thread))
+}
+
+#[get("/details/{target_post_uuid}/likes")]
+async fn get_post_likes(
+    target_post_uuid: web::Path<String>,
+    pagination: web::Query<Pagination>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, actix_web::Error> {
+    use schema::likes::dsl::{
+        created_at as like_created_at, deleted as like_deleted, emoji as like_emoji, likes,
+        post_id as like_post_id,
+    };
+    use schema::posts::dsl::{
+        deleted as post_deleted, posts, published as post_published, uuid as post_uuid,
+    };
+    use schema::users::dsl::{deleted as user_deleted, users};
+
+    let returned_likes = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let target_post_id = match posts
+            .filter(
+                post_uuid
+                    .eq(target_post_uuid.as_str())
+                    .and(post_deleted.eq(false))
+                    .and(post_published.eq(true))
+                    .and(visible_to(current_user.id)),
+            )
+            .select(Post::as_select())
+            .first(&mut conn)
+        {
+            Ok(post) => post.id,
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::NotFound(
+                    "post_not_found",
+                    message!("post-not-found", uuid = target_post_uuid),
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        match likes
+            .inner_join(users)
+            .filter(
+                like_post_id
+                    .eq(target_post_id)
+                    .and(like_emoji.eq(LIKE_REACTION))
+                    .and(like_deleted.eq(false))
+                    .and(user_deleted.eq(false)),
+            )
+            .select((Poster::as_select(), Like::as_select()))
+            .offset(pagination.offset as i64)
+            .limit(pagination.limit as i64)
+            .order_by(like_created_at.desc())
+            .load::<(Poster, Like)>(&mut conn)
+        {
+            Ok(returned_likes) => Ok(returned_likes),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(LikersRead {
+        likes: returned_likes
             .into_iter()
-            .map(|(post, poster, like)| PostRead::from((post, poster, like)))
+            .map(|(poster, like)| LikerRead {
+                poster: PosterRead::from(poster),
+                liked_at: like.created_at.to_string(),
+            })
             .collect(),
     }))
 }
 
+#[get("/bookmarks")]
+async fn get_bookmarks(
+    pagination: web::Query<Pagination>,
+    bookmarks_query: web::Query<BookmarksQuery>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, actix_web::Error> {
+    use schema::bookmark_collections::dsl::{
+        bookmark_collections, deleted as collection_deleted, owner_id, uuid as collection_uuid,
+    };
+    use schema::bookmarks::dsl::{
+        bookmarks, collection_id as bookmark_collection_id, created_at as bookmark_created_at,
+        deleted as bookmark_deleted, id as bookmark_id, post_id as bookmark_post_id,
+        user_id as bookmark_user_id,
+    };
+    use schema::likes::dsl::{
+        deleted as like_deleted, emoji as like_emoji, likes, post_id as like_post_id,
+        user_id as like_user_id,
+    };
+    use schema::posts::dsl::{
+        deleted as post_deleted, id as post_id, posts, published as post_published,
+    };
+    use schema::users::dsl::users;
+
+    let returned_posts = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let mut query = posts
+            .inner_join(users)
+            .left_join(
+                likes.on(like_post_id
+                    .eq(post_id)
+                    .and(like_user_id.eq(current_user.id))
+                    .and(like_emoji.eq(LIKE_REACTION))
+                    .and(like_deleted.eq(false))),
+            )
+            .left_join(
+                bookmarks.on(bookmark_post_id
+                    .eq(post_id)
+                    .and(bookmark_user_id.eq(current_user.id))
+                    .and(bookmark_deleted.eq(false))),
+            )
+            .filter(
+                post_deleted
+                    .eq(false)
+                    .and(post_published.eq(true))
+                    .and(bookmark_id.is_not_null())
+                    .and(visible_to(current_user.id)),
+            )
+            .into_boxed();
+
+        if let Some(target_collection_uuid) = &bookmarks_query.collection {
+            let collection = match bookmark_collections
+                .filter(
+                    collection_uuid
+                        .eq(target_collection_uuid)
+                        .and(owner_id.eq(current_user.id))
+                        .and(collection_deleted.eq(false)),
+                )
+                .select(BookmarkCollection::as_select())
+                .first(&mut conn)
+            {
+                Ok(collection) => collection,
+                Err(diesel::result::Error::NotFound) => {
+                    return Err(ServiceError::NotFound(
+                        "collection_not_found",
+                        message!("collection-not-found", uuid = target_collection_uuid),
+                    ))
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            query = query.filter(bookmark_collection_id.eq(collection.id));
+        }
+
+        match query
+            .select((
+                Post::as_select(),
+                Poster::as_select(),
+                Option::<Like>::as_select(),
+                Option::<Bookmark>::as_select(),
+            ))
+            .offset(pagination.offset as i64)
+            .limit(pagination.limit as i64)
+            .order_by(bookmark_created_at.desc())
+            .load::<PostRow>(&mut conn)
+            .and_then(|returned_posts| load_post_reads(returned_posts, current_user.id, &mut conn))
+            .and_then(|returned_posts| {
+                let preferences = load_preferences(current_user.id, &mut conn)?;
+                Ok(apply_media_preference(
+                    returned_posts,
+                    &preferences.sensitive_media,
+                ))
+            }) {
+            Ok(returned_posts) => Ok(returned_posts),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(FeedRead {
+        posts: returned_posts,
+    }))
+}
+
 pub fn configure(cfg: &mut ServiceConfig) {
     cfg.service(
         web::scope("/feeds")
             .service(get_feed)
             .service(get_post_details)
-            .service(get_replies),
+            .service(get_post_likes)
+            .service(get_replies)
+            .service(get_thread)
+            .service(get_bookmarks),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use microblogs::test_connection;
+
+    use super::*;
+
+    // User 1 blocked user 3, so the reply by user 3 is a placeholder that still
+    // holds the reply under it.
+    fn seed_thread(conn: &mut DbConn) {
+        conn.batch_execute(
+            "INSERT INTO users (id, username, email, real_name, summary, password) VALUES
+                 (1, 'a', 'a@example.com', 'A', '', 'x'),
+                 (2, 'b', 'b@example.com', 'B', '', 'x'),
+                 (3, 'c', 'c@example.com', 'C', '', 'x');
+             INSERT INTO posts (id, uuid, parent_id, poster_id, body, created_at) VALUES
+                 (1, 'top', NULL, 2, 'top', '2024-01-01 00:00:00'),
+                 (2, 'root', 1, 1, 'root', '2024-01-01 00:00:01'),
+                 (3, 'r1', 2, 2, 'r1', '2024-01-01 00:00:02'),
+                 (4, 'r2', 2, 3, 'r2', '2024-01-01 00:00:03'),
+                 (5, 'r3', 2, 2, 'r3', '2024-01-01 00:00:04'),
+                 (6, 'r2a', 4, 2, 'r2a', '2024-01-01 00:00:05'),
+                 (7, 'other', NULL, 2, 'other', '2024-01-01 00:00:06'),
+                 (8, 'o1', 7, 2, 'o1', '2024-01-01 00:00:07');
+             INSERT INTO blocks (blocker_id, blocked_id) VALUES (1, 3);",
+        )
+        .unwrap();
+    }
+
+    fn nodes(
+        target_post_uuid: &str,
+        max_depth: i32,
+        branch_limit: i32,
+        current_user_id: i32,
+        conn: &mut DbConn,
+    ) -> Vec<(i32, i32, bool)> {
+        load_thread_nodes(
+            target_post_uuid,
+            max_depth,
+            branch_limit,
+            current_user_id,
+            conn,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|node| (node.id, node.depth, node.visible))
+        .collect()
+    }
+
+    #[test]
+    fn loads_thread_nodes() {
+        let mut conn = test_connection();
+        seed_thread(&mut conn);
+
+        assert_eq!(
+            nodes("root", 5, 10, 1, &mut conn),
+            vec![
+                (1, -1, true),
+                (2, 0, true),
+                (3, 1, true),
+                (4, 1, false),
+                (5, 1, true),
+                (6, 2, true),
+            ]
+        );
+        assert_eq!(
+            nodes("root", 1, 1, 1, &mut conn),
+            vec![(1, -1, true), (2, 0, true), (3, 1, true)]
+        );
+        assert_eq!(
+            nodes("root", 0, 10, 3, &mut conn),
+            vec![(1, -1, true), (2, 0, false)]
+        );
+        assert!(nodes("missing", 5, 10, 1, &mut conn).is_empty());
+    }
+}
@@ -0,0 +1,398 @@
+use actix_web::{
+    delete, get, post,
+    web::{self, ServiceConfig},
+    Error, HttpResponse,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, Insertable, QueryDsl, Queryable, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use microblogs::{errors::ServiceError, generate_uid, message, schema, DbConn, DbPool, WritePool};
+use serde::{Deserialize, Serialize};
+
+use crate::users::UserDetails;
+
+const MAX_PHRASE_LENGTH: usize = 128;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum FilterKind {
+    Word,
+    Phrase,
+    Hashtag,
+}
+
+impl FilterKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FilterKind::Word => "word",
+            FilterKind::Phrase => "phrase",
+            FilterKind::Hashtag => "hashtag",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum FilterAction {
+    Hide,
+    Warn,
+}
+
+impl FilterAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FilterAction::Hide => "hide",
+            FilterAction::Warn => "warn",
+        }
+    }
+}
+
+fn default_whole_word() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct FilterCreate {
+    phrase: String,
+    kind: FilterKind,
+    #[serde(default = "default_whole_word")]
+    whole_word: bool,
+    action: FilterAction,
+    expires_in: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::filters)]
+struct NewFilter<'a> {
+    pub uuid: String,
+    pub owner_id: i32,
+    pub phrase: &'a str,
+    pub kind: &'a str,
+    pub whole_word: bool,
+    pub action: &'a str,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::filters)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+pub struct Filter {
+    pub uuid: String,
+    pub phrase: String,
+    pub kind: String,
+    pub whole_word: bool,
+    pub action: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Filter {
+    pub fn hides(&self) -> bool {
+        self.action == FilterAction::Hide.as_str()
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        let phrase = self.phrase.to_lowercase();
+
+        if self.kind == FilterKind::Hashtag.as_str() {
+            return text
+                .split(|c: char| !(is_word_char(c) || c == '#'))
+                .any(|word| word.strip_prefix('#') == Some(phrase.as_str()));
+        }
+
+        if !self.whole_word {
+            return text.contains(&phrase);
+        }
+
+        text.match_indices(&phrase).any(|(start, _)| {
+            let before = text[..start].chars().next_back();
+            let after = text[start + phrase.len()..].chars().next();
+            !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+        })
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Serialize)]
+struct FilterRead {
+    uuid: String,
+    phrase: String,
+    kind: String,
+    whole_word: bool,
+    action: String,
+    expires_at: Option<String>,
+    created_at: String,
+}
+
+impl From<Filter> for FilterRead {
+    fn from(filter: Filter) -> Self {
+        FilterRead {
+            uuid: filter.uuid,
+            phrase: filter.phrase,
+            kind: filter.kind,
+            whole_word: filter.whole_word,
+            action: filter.action,
+            expires_at: filter.expires_at.map(|expires_at| expires_at.to_string()),
+            created_at: filter.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FiltersRead {
+    filters: Vec<FilterRead>,
+}
+
+// Loads the filters of a user that have not expired yet.
+pub fn load_filters(
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Vec<Filter>, diesel::result::Error> {
+    use schema::filters::dsl::{deleted, expires_at, filters, owner_id};
+
+    filters
+        .filter(
+            owner_id.eq(current_user_id).and(deleted.eq(false)).and(
+                expires_at
+                    .is_null()
+                    .or(expires_at.gt(Utc::now().naive_utc())),
+            ),
+        )
+        .select(Filter::as_select())
+        .load(conn)
+}
+
+#[post("/create")]
+async fn create_filter(
+    info: web::Json<FilterCreate>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::filters::dsl::filters;
+
+    let phrase = info.phrase.trim();
+    // hashtags are stored without their `#`, other filters match it literally
+    let phrase = match info.kind {
+        FilterKind::Hashtag => phrase.strip_prefix('#').unwrap_or(phrase),
+        FilterKind::Word | FilterKind::Phrase => phrase,
+    }
+    .to_string();
+    if phrase.is_empty() || phrase.chars().count() > MAX_PHRASE_LENGTH {
+        return Err(ServiceError::BadRequest(
+            "invalid_filter_phrase",
+            message!("invalid-filter-phrase-length", max = MAX_PHRASE_LENGTH),
+        )
+        .into());
+    }
+    if info.kind != FilterKind::Phrase && phrase.contains(char::is_whitespace) {
+        return Err(ServiceError::BadRequest(
+            "invalid_filter_phrase",
+            message!("filter-phrase-with-spaces"),
+        )
+        .into());
+    }
+    if let Some(expires_in) = info.expires_in {
+        if expires_in <= 0 {
+            return Err(ServiceError::BadRequest(
+                "invalid_filter_duration",
+                message!("invalid-filter-duration"),
+            )
+            .into());
+        }
+    }
+
+    let filter = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let new_filter = NewFilter {
+            uuid: generate_uid(),
+            owner_id: current_user.id,
+            phrase: &phrase,
+            kind: info.kind.as_str(),
+            whole_word: info.whole_word,
+            action: info.action.as_str(),
+            expires_at: info
+                .expires_in
+                .map(|expires_in| Utc::now().naive_utc() + Duration::seconds(expires_in)),
+        };
+
+        match diesel::insert_into(filters)
+            .values(&new_filter)
+            .returning(Filter::as_returning())
+            .get_result(&mut conn)
+        {
+            Ok(filter) => Ok(filter),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(FilterRead::from(filter)))
+}
+
+#[get("/list")]
+async fn list_filters(
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    let returned_filters = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match load_filters(current_user.id, &mut conn) {
+            Ok(returned_filters) => Ok(returned_filters),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(FiltersRead {
+        filters: returned_filters.into_iter().map(FilterRead::from).collect(),
+    }))
+}
+
+#[delete("/{target_filter_uuid}")]
+async fn delete_filter(
+    target_filter_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::filters::dsl::{deleted, filters, owner_id, uuid};
+
+    let filter = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match diesel::update(filters)
+            .filter(
+                uuid.eq(target_filter_uuid.as_str())
+                    .and(owner_id.eq(current_user.id))
+                    .and(deleted.eq(false)),
+            )
+            .set(deleted.eq(true))
+            .returning(Filter::as_returning())
+            .get_result(&mut conn)
+        {
+            Ok(filter) => Ok(filter),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "filter_not_found",
+                message!("filter-not-found", uuid = target_filter_uuid),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(FilterRead::from(filter)))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/filters")
+            .service(create_filter)
+            .service(list_filters)
+            .service(delete_filter),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use microblogs::test_connection;
+
+    use super::*;
+
+    fn filter(phrase: &str, kind: FilterKind, whole_word: bool) -> Filter {
+        Filter {
+            uuid: "filter".to_string(),
+            phrase: phrase.to_string(),
+            kind: kind.as_str().to_string(),
+            whole_word,
+            action: FilterAction::Hide.as_str().to_string(),
+            expires_at: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn matches_whole_words_ignoring_case() {
+        let word = filter("Spoiler", FilterKind::Word, true);
+        assert!(word.matches("no SPOILERS here, just a spoiler."));
+        assert!(word.matches("spoiler"));
+        assert!(!word.matches("spoilers ahead"));
+        assert!(!word.matches("unspoiler_ed"));
+
+        let phrase = filter("season finale", FilterKind::Phrase, true);
+        assert!(phrase.matches("The Season Finale was great"));
+        assert!(!phrase.matches("the season finales"));
+    }
+
+    #[test]
+    fn matches_parts_of_words_when_asked() {
+        let word = filter("spoil", FilterKind::Word, false);
+        assert!(word.matches("UNSPOILED"));
+        assert!(!word.matches("spool"));
+    }
+
+    #[test]
+    fn matches_hashtags_only() {
+        let hashtag = filter("rust", FilterKind::Hashtag, true);
+        assert!(hashtag.matches("learning #Rust today"));
+        assert!(hashtag.matches("(#rust)"));
+        assert!(!hashtag.matches("rust without a tag"));
+        assert!(!hashtag.matches("#rustacean"));
+    }
+
+    #[test]
+    fn skips_expired_filters() {
+        let mut conn = test_connection();
+        conn.batch_execute(
+            "INSERT INTO users (id, username, email, real_name, summary, password) VALUES
+                 (1, 'a', 'a@example.com', 'A', '', 'x'),
+                 (2, 'b', 'b@example.com', 'B', '', 'x');
+             INSERT INTO filters (uuid, owner_id, phrase, kind, whole_word, action, expires_at)
+             VALUES
+                 ('forever', 1, 'a', 'word', TRUE, 'hide', NULL),
+                 ('later', 1, 'b', 'word', TRUE, 'hide', '2999-01-01 00:00:00'),
+                 ('expired', 1, 'c', 'word', TRUE, 'hide', '2000-01-01 00:00:00'),
+                 ('other', 2, 'd', 'word', TRUE, 'hide', NULL);",
+        )
+        .unwrap();
+
+        let mut loaded: Vec<String> = load_filters(1, &mut conn)
+            .unwrap()
+            .into_iter()
+            .map(|filter| filter.uuid)
+            .collect();
+        loaded.sort();
+        assert_eq!(loaded, vec!["forever", "later"]);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fs, path::Path, time::Duration};
+
+use actix_web::{
+    get,
+    web::{self, ServiceConfig},
+    HttpResponse,
+};
+use diesel::RunQueryDsl;
+use microblogs::{generate_uid, migrations, AppState, DbPool, WritePool};
+use serde::Serialize;
+
+// Kept short so a stuck database fails the probe instead of hanging it.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+struct HealthRead {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReadinessRead {
+    ready: bool,
+    database: bool,
+    migrations: bool,
+    uploads: bool,
+}
+
+#[derive(Serialize)]
+struct VersionRead {
+    version: &'static str,
+    git_hash: &'static str,
+    features: Vec<&'static str>,
+}
+
+// Checks the database answers a trivial query and has no pending migrations.
+// Failures are logged rather than returned, as the endpoint is public.
+fn check_database(pool: &DbPool) -> (bool, bool) {
+    let mut conn = match pool.get_timeout(READINESS_TIMEOUT) {
+        Ok(conn) => conn,
+        Err(err) => {
+            log::warn!("Readiness: no database connection: {}", err);
+            return (false, false);
+        }
+    };
+
+    if let Err(err) = diesel::sql_query("SELECT 1").execute(&mut conn) {
+        log::warn!("Readiness: database query failed: {}", err);
+        return (false, false);
+    }
+
+    match migrations::is_current(&mut conn) {
+        Ok(true) => (true, true),
+        Ok(false) => {
+            log::warn!("Readiness: migrations are pending");
+            (true, false)
+        }
+        Err(err) => {
+            log::warn!("Readiness: could not check the migrations: {}", err);
+            (true, false)
+        }
+    }
+}
+
+// Writes go through their own pool, a single connection on SQLite, so it can
+// be exhausted while reads still work.
+fn check_write_pool(write_pool: &WritePool) -> bool {
+    match write_pool.get_timeout(READINESS_TIMEOUT) {
+        Ok(_) => true,
+        Err(err) => {
+            log::warn!("Readiness: no database connection for writes: {}", err);
+            false
+        }
+    }
+}
+
+fn check_uploads(uploads_dir: &str) -> bool {
+    let probe = Path::new(uploads_dir).join(format!(".readyz-{}", generate_uid()));
+    let result = fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe));
+    if let Err(err) = &result {
+        log::warn!(
+            "Readiness: uploads directory {} is not writable: {}",
+            uploads_dir,
+            err
+        );
+    }
+    result.is_ok()
+}
+
+#[get("/healthz")]
+async fn get_health() -> HttpResponse {
+    HttpResponse::Ok().json(HealthRead { status: "ok" })
+}
+
+#[get("/readyz")]
+async fn get_readiness(
+    pool: web::Data<DbPool>,
+    write_pool: web::Data<WritePool>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let uploads_dir = app_state.uploads_dir.clone();
+    let checks = web::block(move || {
+        (
+            check_database(&pool),
+            check_write_pool(&write_pool),
+            check_uploads(&uploads_dir),
+        )
+    })
+    .await;
+    let ((database, migrations), writes, uploads) =
+        checks.unwrap_or(((false, false), false, false));
+    let database = database && writes;
+
+    let readiness = ReadinessRead {
+        ready: database && migrations && uploads,
+        database,
+        migrations,
+        uploads,
+    };
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[get("/version")]
+async fn get_version() -> HttpResponse {
+    let mut features = Vec::new();
+    if cfg!(feature = "sqlite") {
+        features.push("sqlite");
+    }
+    if cfg!(feature = "postgres") {
+        features.push("postgres");
+    }
+
+    HttpResponse::Ok().json(VersionRead {
+        version: env!("CARGO_PKG_VERSION"),
+        // set by build.rs, "unknown" when built outside a git checkout
+        git_hash: env!("GIT_HASH"),
+        features,
+    })
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(get_health)
+        .service(get_readiness)
+        .service(get_version);
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Reply,
+    Like,
+    Reaction,
+    Repost,
+    Quote,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    NewPost {
+        #[serde(skip)]
+        poster_id: i32,
+        #[serde(skip)]
+        visibility: String,
+        uuid: String,
+        poster_username: String,
+    },
+    PostCounts {
+        #[serde(skip)]
+        poster_id: i32,
+        // public and unlisted posts, whose counts anyone may see
+        #[serde(skip)]
+        open: bool,
+        uuid: String,
+        reply_count: i32,
+        like_count: i32,
+        repost_count: i32,
+        quote_count: i32,
+    },
+    Notification {
+        #[serde(skip)]
+        recipient_id: i32,
+        kind: NotificationKind,
+        post_uuid: String,
+        actor_username: String,
+    },
+}
+
+// The relationships of a subscriber that decide which new posts reach them,
+// following the same rules as timelines.
+#[derive(Default)]
+pub struct Subscriber {
+    pub user_id: i32,
+    pub followee_ids: HashSet<i32>,
+    // users the subscriber blocked or muted, or who blocked them
+    pub hidden_ids: HashSet<i32>,
+}
+
+impl Event {
+    pub fn is_visible_to(&self, subscriber: &Subscriber) -> bool {
+        let user_id = subscriber.user_id;
+        match self {
+            Event::PostCounts {
+                poster_id, open, ..
+            } => *open || *poster_id == user_id,
+            Event::Notification { recipient_id, .. } => *recipient_id == user_id,
+            Event::NewPost {
+                poster_id,
+                visibility,
+                ..
+            } => {
+                if *poster_id == user_id {
+                    return true;
+                }
+                if subscriber.hidden_ids.contains(poster_id) {
+                    return false;
+                }
+                match visibility.as_str() {
+                    "public" => true,
+                    "followers" => subscriber.followee_ids.contains(poster_id),
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        EventHub { sender }
+    }
+
+    pub fn publish(&self, event: Event) {
+        // sending only fails when nobody is listening, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_post(poster_id: i32, visibility: &str) -> Event {
+        Event::NewPost {
+            poster_id,
+            visibility: visibility.to_string(),
+            uuid: "abcdefgh".to_string(),
+            poster_username: "poster".to_string(),
+        }
+    }
+
+    fn subscriber(followee_ids: &[i32], hidden_ids: &[i32]) -> Subscriber {
+        Subscriber {
+            user_id: 1,
+            followee_ids: followee_ids.iter().copied().collect(),
+            hidden_ids: hidden_ids.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn new_posts_reach_their_audience() {
+        assert!(new_post(2, "public").is_visible_to(&subscriber(&[], &[])));
+        assert!(!new_post(2, "unlisted").is_visible_to(&subscriber(&[2], &[])));
+        assert!(!new_post(2, "followers").is_visible_to(&subscriber(&[], &[])));
+        assert!(new_post(2, "followers").is_visible_to(&subscriber(&[2], &[])));
+        assert!(!new_post(2, "direct").is_visible_to(&subscriber(&[2], &[])));
+        assert!(new_post(1, "direct").is_visible_to(&subscriber(&[], &[])));
+    }
+
+    #[test]
+    fn new_posts_skip_hidden_posters() {
+        assert!(!new_post(2, "public").is_visible_to(&subscriber(&[], &[2])));
+        assert!(!new_post(2, "followers").is_visible_to(&subscriber(&[2], &[2])));
+    }
+
+    #[test]
+    fn blocked_subscribers_get_no_new_posts() {
+        let hub = EventHub::new();
+        let mut receiver = hub.subscribe();
+        let blocked = subscriber(&[], &[2]);
+
+        hub.publish(new_post(2, "public"));
+        hub.publish(new_post(3, "public"));
+
+        let delivered: Vec<String> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .filter(|event| event.is_visible_to(&blocked))
+            .filter_map(|event| match event {
+                Event::NewPost { poster_id, .. } => Some(poster_id.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(delivered, vec!["3"]);
+    }
+}
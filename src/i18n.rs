@@ -0,0 +1,217 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    future::{ready, Future, Ready},
+    pin::Pin,
+};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::ACCEPT_LANGUAGE,
+    Error,
+};
+use fluent_templates::{fluent_bundle::FluentValue, static_loader, LanguageIdentifier, Loader};
+
+pub const DEFAULT_LOCALE: &str = "pt-BR";
+pub const SUPPORTED_LOCALES: [&str; 2] = ["pt-BR", "en-US"];
+
+static_loader! {
+    static LOCALES = {
+        locales: "./locales",
+        fallback_language: "pt-BR",
+        // keeps Unicode isolation marks out of the messages sent as JSON
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+tokio::task_local! {
+    static LOCALE: RefCell<&'static str>;
+}
+
+// A user-facing message from the catalogs in `locales`, rendered in the locale
+// of the request it is sent in.
+#[derive(Debug, Clone)]
+pub struct Message {
+    id: &'static str,
+    args: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn new(id: &'static str, args: Vec<(&'static str, String)>) -> Self {
+        Message { id, args }
+    }
+
+    pub fn render(&self, locale: &str) -> String {
+        let language: LanguageIdentifier = locale
+            .parse()
+            .unwrap_or_else(|_| DEFAULT_LOCALE.parse().unwrap());
+        let args: HashMap<Cow<'static, str>, FluentValue> = self
+            .args
+            .iter()
+            .map(|(name, value)| (Cow::Borrowed(*name), FluentValue::from(value.clone())))
+            .collect();
+        LOCALES.lookup_with_args(&language, self.id, &args)
+    }
+}
+
+// Builds a `Message`, as in `message!("post-not-found", uuid = post_uuid)`. The
+// arguments are formatted with `Display`.
+#[macro_export]
+macro_rules! message {
+    ($id:literal) => {
+        $crate::i18n::Message::new($id, Vec::new())
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::Message::new($id, vec![$((stringify!($name), ($value).to_string())),+])
+    };
+}
+
+// The locale of the request being handled, or the default one outside of
+// `Localize`.
+pub fn current_locale() -> &'static str {
+    LOCALE
+        .try_with(|locale| *locale.borrow())
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+// Switches the current request to the locale a user picked in their
+// preferences. Unsupported locales are ignored.
+pub fn set_locale(locale: &str) {
+    if let Some(supported) = supported_locale(locale) {
+        let _ = LOCALE.try_with(|current| *current.borrow_mut() = supported);
+    }
+}
+
+pub fn supported_locale(locale: &str) -> Option<&'static str> {
+    SUPPORTED_LOCALES
+        .iter()
+        .find(|supported| supported.eq_ignore_ascii_case(locale))
+        .copied()
+}
+
+// Picks the supported locale the client prefers the most, matching on the
+// language alone when the region differs, so `en-GB` still gets `en-US`.
+fn negotiate(accept_language: &str) -> Option<&'static str> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ranges.into_iter().find_map(|(tag, _)| {
+        supported_locale(tag).or_else(|| {
+            let language = tag.split('-').next()?;
+            SUPPORTED_LOCALES
+                .iter()
+                .find(|supported| {
+                    supported
+                        .split('-')
+                        .next()
+                        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(language))
+                })
+                .copied()
+        })
+    })
+}
+
+// Chooses the locale of each request from its `Accept-Language` header. Signed
+// in users can override it with their locale preference.
+pub struct Localize;
+
+impl<S, B> Transform<S, ServiceRequest> for Localize
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LocalizeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LocalizeMiddleware { service }))
+    }
+}
+
+pub struct LocalizeMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for LocalizeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let locale = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|accept_language| accept_language.to_str().ok())
+            .and_then(negotiate)
+            .unwrap_or(DEFAULT_LOCALE);
+
+        Box::pin(LOCALE.scope(RefCell::new(locale), self.service.call(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the locale `Localize` picks for a request with the given header
+    fn locale_for(accept_language: &str) -> &'static str {
+        negotiate(accept_language).unwrap_or(DEFAULT_LOCALE)
+    }
+
+    #[test]
+    fn picks_the_preferred_supported_locale() {
+        assert_eq!(locale_for("en-US"), "en-US");
+        assert_eq!(locale_for("pt-br"), "pt-BR");
+        assert_eq!(locale_for("fr-FR, en-US, pt-BR"), "en-US");
+    }
+
+    #[test]
+    fn orders_ranges_by_quality() {
+        assert_eq!(locale_for("en-US;q=0.5, pt-BR;q=0.8"), "pt-BR");
+        assert_eq!(locale_for("pt-BR;q=0.1, fr;q=0.9, en-US"), "en-US");
+        // ranges without a quality are preferred the most
+        assert_eq!(locale_for("en-US;q=0.9, pt-BR"), "pt-BR");
+        // a quality of zero rules a locale out
+        assert_eq!(locale_for("en-US;q=0, fr"), "pt-BR");
+    }
+
+    #[test]
+    fn matches_on_the_language_alone() {
+        assert_eq!(locale_for("en"), "en-US");
+        assert_eq!(locale_for("en-GB"), "en-US");
+        assert_eq!(locale_for("pt-PT;q=0.9, de"), "pt-BR");
+        assert_eq!(locale_for("fr-CA, EN-au;q=0.3"), "en-US");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_locale() {
+        assert_eq!(negotiate("fr-FR, de;q=0.5"), None);
+        assert_eq!(locale_for("fr-FR, de;q=0.5"), "pt-BR");
+        assert_eq!(locale_for(""), "pt-BR");
+        assert_eq!(locale_for("*"), "pt-BR");
+        assert_eq!(locale_for("en-US;q=oops"), "en-US");
+    }
+}
@@ -1,19 +1,91 @@
-use diesel::{
-    r2d2::{self, ConnectionManager, PooledConnection},
-    SqliteConnection,
+use std::{ops::Deref, time::Duration};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
 };
+use diesel::r2d2::{self, ConnectionManager, PooledConnection};
 use rand::Rng;
 use serde::Deserialize;
 
-pub type DbConn = PooledConnection<ConnectionManager<SqliteConnection>>;
-pub type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("the `sqlite` and `postgres` features can't be enabled together");
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("one of the `sqlite` or `postgres` features must be enabled");
+
+// The database backend picked at build time by the `sqlite` (default) or
+// `postgres` feature.
+#[cfg(feature = "sqlite")]
+pub type DbBackend = diesel::sqlite::Sqlite;
+#[cfg(feature = "sqlite")]
+pub type DbConnection = diesel::SqliteConnection;
+#[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+pub type DbBackend = diesel::pg::Pg;
+#[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+pub type DbConnection = diesel::PgConnection;
+
+pub type DbConn = PooledConnection<ConnectionManager<DbConnection>>;
+pub type DbPool = r2d2::Pool<ConnectionManager<DbConnection>>;
+
+// Connections for handlers that write. SQLite allows one writer at a time, so
+// there this is a pool of a single connection that makes concurrent writers
+// wait their turn instead of failing with `database is locked`. Other backends
+// share the main pool.
+#[derive(Clone)]
+pub struct WritePool(DbPool);
+
+impl Deref for WritePool {
+    type Target = DbPool;
+
+    fn deref(&self) -> &DbPool {
+        &self.0
+    }
+}
 
+pub mod config;
 pub mod errors;
+pub mod hub;
+pub mod i18n;
+pub mod markup;
+pub mod migrations;
+pub mod request_id;
 pub mod schema;
+pub mod text;
+
+pub const LIKE_REACTION: &str = "❤️";
+pub const DEFAULT_REACTIONS: &str = "❤️,👍,😂,😮,😢,🎉";
+pub const DEFAULT_MAX_PINNED_POSTS: i64 = 3;
+pub const DEFAULT_MAX_POST_LENGTH: usize = 500;
+// The width of the `posts.body` column. Post lengths are counted in graphemes,
+// which may take several characters each, such as emoji sequences.
+pub const MAX_POST_BODY_CHARS: usize = 1024;
+pub const DEFAULT_LINK_PREVIEW_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_LINK_PREVIEW_MAX_PAGE_SIZE: usize = 1024 * 1024;
+// How long an SQLite connection waits for another process holding the write
+// lock, such as the admin tool, before giving up.
+pub const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How pages are fetched for link previews. Private hosts are refused unless
+// allowed, which is only meant for local development and tests.
+#[derive(Clone)]
+pub struct LinkPreviewConfig {
+    pub enabled: bool,
+    pub timeout: Duration,
+    pub max_page_size: usize,
+    pub user_agent: String,
+    pub allow_private_hosts: bool,
+}
 
 pub struct AppState {
     pub secret_key: String,
+    pub token_lifetime: chrono::Duration,
+    pub registrations_open: bool,
     pub uploads_dir: String,
+    pub max_upload_files: usize,
+    pub reaction_emojis: Vec<String>,
+    pub max_pinned_posts: i64,
+    pub max_post_length: usize,
+    pub link_previews: LinkPreviewConfig,
 }
 
 #[derive(Deserialize)]
@@ -22,11 +94,72 @@ pub struct Pagination {
     pub limit: i32,
 }
 
+// Applied to every SQLite connection the pools open. WAL lets readers carry on
+// while a write is in progress, and NORMAL synchronous is safe in WAL mode.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqliteTuning;
+
+#[cfg(feature = "sqlite")]
+impl r2d2::CustomizeConnection<DbConnection, r2d2::Error> for SqliteTuning {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {};
+             PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA foreign_keys = ON;",
+            SQLITE_BUSY_TIMEOUT.as_millis()
+        ))
+        .map_err(r2d2::Error::QueryError)
+    }
+}
+
+pub fn build_pool(database_url: &str, max_size: u32) -> Result<DbPool, r2d2::PoolError> {
+    let manager = ConnectionManager::<DbConnection>::new(database_url);
+    let builder = r2d2::Pool::builder().max_size(max_size);
+    #[cfg(feature = "sqlite")]
+    let builder = builder.connection_customizer(Box::new(SqliteTuning));
+    builder.build(manager)
+}
+
+#[cfg(feature = "sqlite")]
+pub fn build_write_pool(database_url: &str, _pool: &DbPool) -> Result<WritePool, r2d2::PoolError> {
+    build_pool(database_url, 1).map(WritePool)
+}
+
+#[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+pub fn build_write_pool(_database_url: &str, pool: &DbPool) -> Result<WritePool, r2d2::PoolError> {
+    Ok(WritePool(pool.clone()))
+}
+
+// A connection with every migration applied, for tests. SQLite gets a fresh
+// in-memory database; PostgreSQL uses the database in `TEST_DATABASE_URL`
+// inside a transaction that is never committed, so it is left untouched.
+#[cfg(feature = "test-util")]
+pub fn test_connection() -> DbConn {
+    #[cfg(feature = "sqlite")]
+    let database_url = ":memory:".to_string();
+    #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+    let database_url =
+        std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+
+    let mut conn = build_pool(&database_url, 1)
+        .and_then(|pool| pool.get())
+        .expect("failed to connect to the test database");
+    #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+    diesel::Connection::begin_test_transaction(&mut *conn)
+        .expect("failed to start the test transaction");
+    migrations::run_pending(&mut conn).expect("failed to apply the migrations");
+    conn
+}
+
 pub fn generate_uid() -> String {
     const CHARSET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
     const LENGTH: usize = 8;
 
-    let mut rng = rand::rngs::OsRng::default();
+    let mut rng = rand::rngs::OsRng;
     let mut uid = String::with_capacity(LENGTH);
     for _ in 0..LENGTH {
         let idx = rng.gen_range(0..CHARSET.len());
@@ -34,3 +167,9 @@ pub fn generate_uid() -> String {
     }
     uid
 }
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hashed_password = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    Ok(hashed_password.to_string())
+}
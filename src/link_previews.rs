@@ -0,0 +1,698 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+};
+
+use actix_tls::connect::{Connector as TcpConnector, Resolve, Resolver};
+use actix_web::web;
+use awc::{
+    http::{header, Uri},
+    Client, Connector,
+};
+use diesel::{
+    ExpressionMethods, Insertable, QueryDsl, Queryable, RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{
+    markup::{self, Facet, FacetKind},
+    schema, DbConn, LinkPreviewConfig, WritePool,
+};
+use serde::Serialize;
+
+const MAX_REDIRECTS: usize = 3;
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 1024;
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::link_previews)]
+struct NewLinkPreview<'a> {
+    pub url: &'a str,
+    pub title: &'a str,
+    pub description: Option<&'a str>,
+    pub image_url: Option<&'a str>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::link_previews)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LinkPreviewRead {
+    url: String,
+    title: String,
+    description: Option<String>,
+    image_url: Option<String>,
+}
+
+impl From<LinkPreview> for LinkPreviewRead {
+    fn from(preview: LinkPreview) -> Self {
+        Self {
+            url: preview.url,
+            title: preview.title,
+            description: preview.description,
+            image_url: preview.image_url,
+        }
+    }
+}
+
+struct Card {
+    title: String,
+    description: Option<String>,
+    image_url: Option<String>,
+}
+
+// Only the first link of a post gets a preview card.
+pub fn first_link(facets: &[Facet]) -> Option<&str> {
+    facets
+        .iter()
+        .find(|facet| facet.kind == FacetKind::Link)
+        .and_then(|facet| facet.value.as_deref())
+}
+
+pub fn load_link_previews(
+    urls: &[String],
+    conn: &mut DbConn,
+) -> Result<HashMap<String, LinkPreviewRead>, diesel::result::Error> {
+    use schema::link_previews::dsl::{link_previews, url};
+
+    if urls.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let previews: Vec<LinkPreview> = link_previews
+        .filter(url.eq_any(urls))
+        .select(LinkPreview::as_select())
+        .load(conn)?;
+
+    Ok(previews
+        .into_iter()
+        .map(|preview| (preview.url.clone(), LinkPreviewRead::from(preview)))
+        .collect())
+}
+
+// Fetches the preview card of the first link of a post body, unless it is
+// cached already. Runs in the background, so failures are only logged.
+pub fn fetch_in_background(body: &str, pool: WritePool, config: LinkPreviewConfig) {
+    if !config.enabled {
+        return;
+    }
+    let link = match first_link(&markup::render(body).facets) {
+        Some(link) => link.to_string(),
+        None => return,
+    };
+
+    actix_web::rt::spawn(async move {
+        if let Err(err) = fetch_and_store(&link, pool, &config).await {
+            log::warn!("Failed to fetch link preview for {}: {}", link, err);
+        }
+    });
+}
+
+async fn fetch_and_store(
+    link: &str,
+    pool: WritePool,
+    config: &LinkPreviewConfig,
+) -> Result<(), String> {
+    use schema::link_previews::dsl::{link_previews, url};
+
+    let cached_link = link.to_string();
+    let cached_pool = pool.clone();
+    let cached: i64 = web::block(move || {
+        let mut conn = cached_pool.get().map_err(|err| err.to_string())?;
+        link_previews
+            .filter(url.eq(&cached_link))
+            .count()
+            .get_result(&mut conn)
+            .map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+    if cached > 0 {
+        return Ok(());
+    }
+
+    let (page_url, html) = fetch_page(link, config).await?;
+    let card = match extract_card(&html, &page_url) {
+        Some(card) => card,
+        None => return Ok(()),
+    };
+
+    let link = link.to_string();
+    web::block(move || {
+        let mut conn = pool.get().map_err(|err| err.to_string())?;
+        diesel::insert_into(link_previews)
+            .values(&NewLinkPreview {
+                url: &link,
+                title: &card.title,
+                description: card.description.as_deref(),
+                image_url: card.image_url.as_deref(),
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+
+    Ok(())
+}
+
+// Resolves the hosts of link preview requests, refusing those with a private
+// address. The client connects to the addresses returned here, so a host can't
+// pass the check and then resolve to somewhere else for the connection itself.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn lookup<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<SocketAddr>, Box<dyn Error>>> + 'a>> {
+        Box::pin(async move {
+            let host = host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string();
+            let resolved_host = host.clone();
+            let addresses: Vec<SocketAddr> =
+                web::block(move || (resolved_host.as_str(), port).to_socket_addrs())
+                    .await
+                    .map_err(|err| err.to_string())??
+                    .collect();
+
+            if addresses.is_empty() || !addresses.iter().all(|address| is_public_ip(address.ip())) {
+                return Err(format!("{} is not a public host", host).into());
+            }
+            Ok(addresses)
+        })
+    }
+}
+
+fn build_client(config: &LinkPreviewConfig) -> Client {
+    let builder = Client::builder()
+        .timeout(config.timeout)
+        .disable_redirects()
+        .add_default_header((header::USER_AGENT, config.user_agent.clone()));
+    if config.allow_private_hosts {
+        return builder.finish();
+    }
+
+    let resolver = Resolver::custom(PublicResolver);
+    builder
+        .connector(Connector::new().connector(TcpConnector::new(resolver).service()))
+        .finish()
+}
+
+// Fetches an HTML page, following a few redirects by hand so every URL along
+// the way goes through `check_url`.
+async fn fetch_page(link: &str, config: &LinkPreviewConfig) -> Result<(Uri, String), String> {
+    let client = build_client(config);
+
+    let mut current: Uri = link.parse().map_err(|_| "invalid URL".to_string())?;
+    for _ in 0..=MAX_REDIRECTS {
+        check_url(&current, config)?;
+
+        let mut response = client
+            .get(current.clone())
+            .insert_header((header::ACCEPT, "text/html"))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| resolve_url(location, &current))
+                .ok_or_else(|| "redirect without a valid location".to_string())?;
+            current = location
+                .parse()
+                .map_err(|_| "invalid redirect URL".to_string())?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(format!("unexpected status {}", response.status()));
+        }
+
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.contains("text/html"));
+        if !is_html {
+            return Err("not an HTML page".to_string());
+        }
+
+        let body = response
+            .body()
+            .limit(config.max_page_size)
+            .await
+            .map_err(|err| err.to_string())?;
+        return Ok((current, String::from_utf8_lossy(&body).into_owned()));
+    }
+
+    Err("too many redirects".to_string())
+}
+
+// Refuses schemes other than HTTP and private IP addresses, so posts can't be
+// used to probe the server's network. Host names are checked by
+// `PublicResolver` when connecting, as the client skips resolving addresses.
+fn check_url(uri: &Uri, config: &LinkPreviewConfig) -> Result<(), String> {
+    if !matches!(uri.scheme_str(), Some("https") | Some("http")) {
+        return Err(format!("unsupported scheme in {}", uri));
+    }
+    if config.allow_private_hosts {
+        return Ok(());
+    }
+
+    let host = uri
+        .host()
+        .ok_or_else(|| format!("missing host in {}", uri))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) if !is_public_ip(ip) => Err(format!("{} is not a public host", uri)),
+        _ => Ok(()),
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, _, _] = ip.octets();
+            // 100.64.0.0/10 is shared address space used by carrier-grade NATs
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ip(IpAddr::V4(mapped)),
+            None => {
+                let first = ip.segments()[0];
+                // fc00::/7 are unique local and fe80::/10 link-local addresses
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+fn resolve_url(link: &str, base: &Uri) -> Option<String> {
+    let scheme = base.scheme_str()?;
+    let authority = base.authority()?;
+    let resolved = if link.starts_with("//") {
+        format!("{}:{}", scheme, link)
+    } else if link.starts_with('/') {
+        format!("{}://{}{}", scheme, authority, link)
+    } else {
+        link.to_string()
+    };
+
+    if resolved.starts_with("https://") || resolved.starts_with("http://") {
+        Some(resolved)
+    } else {
+        None
+    }
+}
+
+// Reads the OpenGraph and Twitter card tags of a page, falling back to its
+// `<title>` and description meta tag. Pages without a title get no card.
+fn extract_card(html: &str, page_url: &Uri) -> Option<Card> {
+    let lowercase = html.to_ascii_lowercase();
+    let head_end = lowercase.find("</head>").unwrap_or(html.len());
+    let (html, lowercase) = (&html[..head_end], &lowercase[..head_end]);
+
+    let tags = meta_tags(html, lowercase);
+    let meta = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            tags.iter()
+                .find(|tag| {
+                    tag.get("property")
+                        .or_else(|| tag.get("name"))
+                        .is_some_and(|name| name.eq_ignore_ascii_case(key))
+                })
+                .and_then(|tag| tag.get("content"))
+                .map(|content| clean(content))
+                .filter(|content| !content.is_empty())
+        })
+    };
+
+    let title = meta(&["og:title", "twitter:title"]).or_else(|| {
+        let start = lowercase.find("<title")?;
+        let start = start + lowercase[start..].find('>')? + 1;
+        let end = start + lowercase[start..].find("</title")?;
+        Some(clean(&decode_entities(&html[start..end]))).filter(|title| !title.is_empty())
+    })?;
+    let description = meta(&["og:description", "twitter:description", "description"]);
+    let image_url = meta(&[
+        "og:image",
+        "og:image:url",
+        "twitter:image",
+        "twitter:image:src",
+    ])
+    .and_then(|image_url| resolve_url(&image_url, page_url));
+
+    Some(Card {
+        title: title.chars().take(MAX_TITLE_LENGTH).collect(),
+        description: description
+            .map(|description| description.chars().take(MAX_DESCRIPTION_LENGTH).collect()),
+        image_url,
+    })
+}
+
+// `lowercase` is `html` lowercased with `to_ascii_lowercase`, which keeps byte
+// offsets the same, so tags can be searched case-insensitively.
+fn meta_tags(html: &str, lowercase: &str) -> Vec<HashMap<String, String>> {
+    let mut tags = Vec::new();
+    let mut rest = 0;
+    while let Some(found) = lowercase[rest..].find("<meta") {
+        let start = rest + found + "<meta".len();
+        let end = match lowercase[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        tags.push(attributes(&html[start..end]));
+        rest = end;
+    }
+    tags
+}
+
+fn attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = tag;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            Some(quoted) => {
+                let quoted = quoted.trim_start();
+                match quoted.chars().next() {
+                    Some(quote @ ('"' | '\'')) => match quoted[1..].find(quote) {
+                        Some(end) => {
+                            rest = &quoted[end + 2..];
+                            &quoted[1..end + 1]
+                        }
+                        None => {
+                            rest = "";
+                            &quoted[1..]
+                        }
+                    },
+                    _ => {
+                        let end = quoted.find(char::is_whitespace).unwrap_or(quoted.len());
+                        rest = &quoted[end..];
+                        &quoted[..end]
+                    }
+                }
+            }
+            None => "",
+        };
+        attributes.insert(name, decode_entities(value));
+    }
+    attributes
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 8)
+            .map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix('#')
+                .and_then(|code| match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => code.parse().ok(),
+                })
+                .and_then(char::from_u32),
+        });
+
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::{
+        rt,
+        web::{self, Bytes},
+        App, HttpResponse, HttpServer,
+    };
+
+    use super::*;
+
+    fn config(allow_private_hosts: bool) -> LinkPreviewConfig {
+        LinkPreviewConfig {
+            enabled: true,
+            timeout: Duration::from_secs(5),
+            max_page_size: 1024,
+            user_agent: "microblogs-test".to_string(),
+            allow_private_hosts,
+        }
+    }
+
+    fn page_url() -> Uri {
+        "https://example.com/posts/1".parse().unwrap()
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode_entities("&#x41;&#X42;&#67;"), "ABC");
+        assert_eq!(decode_entities("Tom &amp; Jerry&apos;s"), "Tom & Jerry's");
+        assert_eq!(decode_entities("&unknown; & &#xzz;"), "&unknown; & &#xzz;");
+        assert_eq!(decode_entities("trailing &"), "trailing &");
+        assert_eq!(decode_entities("&#x1F600;"), "\u{1F600}");
+    }
+
+    #[test]
+    fn parses_attributes() {
+        let parsed = attributes(r#" PROPERTY="og:title" content='Hello &quot;world&quot;' /"#);
+        assert_eq!(parsed["property"], "og:title");
+        assert_eq!(parsed["content"], "Hello \"world\"");
+
+        let parsed = attributes("name=description content=plain async");
+        assert_eq!(parsed["name"], "description");
+        assert_eq!(parsed["content"], "plain");
+        assert_eq!(parsed["async"], "");
+
+        let parsed = attributes(r#"name="og:title" content="never closed"#);
+        assert_eq!(parsed["name"], "og:title");
+        assert_eq!(parsed["content"], "never closed");
+    }
+
+    #[test]
+    fn extracts_open_graph_card() {
+        let html = r#"<html><head>
+            <meta property="og:title" content="  A   title ">
+            <meta property="og:description" content="Some &amp; more">
+            <meta property="og:image" content="/image.png">
+            <title>Ignored</title>
+            </head><body><meta property="og:title" content="Body"></body></html>"#;
+        let card = extract_card(html, &page_url()).unwrap();
+        assert_eq!(card.title, "A title");
+        assert_eq!(card.description.as_deref(), Some("Some & more"));
+        assert_eq!(
+            card.image_url.as_deref(),
+            Some("https://example.com/image.png")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_title_without_head_end() {
+        let html = "<HTML><TITLE>Plain &#x26; simple</TITLE>\
+            <meta name=description content=short>";
+        let card = extract_card(html, &page_url()).unwrap();
+        assert_eq!(card.title, "Plain & simple");
+        assert_eq!(card.description.as_deref(), Some("short"));
+        assert_eq!(card.image_url, None);
+    }
+
+    #[test]
+    fn skips_pages_without_title() {
+        assert!(extract_card("<html><head></head></html>", &page_url()).is_none());
+        assert!(extract_card("<title>   </title>", &page_url()).is_none());
+        assert!(extract_card("<title>unterminated", &page_url()).is_none());
+    }
+
+    #[test]
+    fn drops_unsafe_image_urls() {
+        let html = r#"<meta property="og:title" content="t"><meta property="og:image" content="javascript:alert(1)">"#;
+        let card = extract_card(html, &page_url()).unwrap();
+        assert_eq!(card.image_url, None);
+    }
+
+    #[test]
+    fn classifies_ip_addresses() {
+        let public = ["8.8.8.8", "100.128.0.1", "2001:4860::8888"];
+        let private = [
+            "127.0.0.1",
+            "10.0.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.127.255.255",
+            "0.0.0.0",
+            "::1",
+            "::ffff:10.0.0.1",
+            "::ffff:127.0.0.1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "febf::1",
+        ];
+        for ip in public {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} is public", ip);
+        }
+        for ip in private {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} is private", ip);
+        }
+    }
+
+    #[test]
+    fn refuses_private_urls() {
+        let check = |url: &str| check_url(&url.parse().unwrap(), &config(false));
+        assert!(check("https://example.com/").is_ok());
+        assert!(check("http://8.8.8.8/").is_ok());
+        assert!(check("ftp://example.com/").is_err());
+        assert!(check("http://127.0.0.1/").is_err());
+        assert!(check("http://[::ffff:10.0.0.1]/").is_err());
+        assert!(check("http://[fe80::1]/").is_err());
+        assert!(check_url(&"http://127.0.0.1/".parse().unwrap(), &config(true)).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn resolver_refuses_private_hosts() {
+        assert!(PublicResolver.lookup("localhost", 80).await.is_err());
+        assert!(PublicResolver.lookup("[::1]", 80).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn client_refuses_private_hosts_by_name() {
+        let error = fetch_page("http://localhost:9/", &config(false))
+            .await
+            .unwrap_err();
+        assert!(error.contains("not a public host"), "{}", error);
+    }
+
+    #[actix_web::test]
+    async fn fetches_pages_from_server() {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/page",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("text/html; charset=utf-8")
+                            .body("<title>Page</title>")
+                    }),
+                )
+                .route(
+                    "/redirect",
+                    web::get().to(|| async {
+                        HttpResponse::Found()
+                            .insert_header((header::LOCATION, "/page"))
+                            .finish()
+                    }),
+                )
+                .route(
+                    "/loop",
+                    web::get().to(|| async {
+                        HttpResponse::Found()
+                            .insert_header((header::LOCATION, "/loop"))
+                            .finish()
+                    }),
+                )
+                .route(
+                    "/image",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("image/png")
+                            .body(Bytes::from_static(b"\x89PNG"))
+                    }),
+                )
+                .route(
+                    "/big",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("text/html")
+                            .body("a".repeat(4096))
+                    }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let handle = server.run();
+        let server_handle = handle.handle();
+        rt::spawn(handle);
+
+        let config = config(true);
+        let url = |path: &str| format!("http://{}{}", address, path);
+
+        let (page_url, html) = fetch_page(&url("/redirect"), &config).await.unwrap();
+        assert_eq!(page_url.path(), "/page");
+        assert_eq!(html, "<title>Page</title>");
+
+        let error = fetch_page(&url("/image"), &config).await.unwrap_err();
+        assert_eq!(error, "not an HTML page");
+        let error = fetch_page(&url("/loop"), &config).await.unwrap_err();
+        assert_eq!(error, "too many redirects");
+        assert!(fetch_page(&url("/big"), &config).await.is_err());
+        assert!(fetch_page(&url("/missing"), &config).await.is_err());
+
+        server_handle.stop(true).await;
+    }
+}
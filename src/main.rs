@@ -1,17 +1,145 @@
-use std::{env, fs::create_dir, path::Path};
+use std::{fs::create_dir_all, path::PathBuf, process, time::Duration};
 
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, web};
-use diesel::{r2d2, SqliteConnection};
+use actix_multipart::{form::MultipartFormConfig, MultipartError};
+use actix_web::{
+    error::{JsonPayloadError, PayloadError, QueryPayloadError},
+    middleware::Logger,
+    web, HttpRequest,
+};
+use clap::{Parser, Subcommand};
 use dotenvy::dotenv;
 use env_logger::Env;
-use microblogs::AppState;
+use microblogs::{
+    build_pool, build_write_pool,
+    config::{Config, ConfigOverrides},
+    errors::ServiceError,
+    hub::EventHub,
+    i18n::Localize,
+    message, migrations,
+    request_id::RequestId,
+    AppState, DbPool, LinkPreviewConfig,
+};
 
 mod attachments;
+mod bookmarks;
+mod drafts;
+mod events;
 mod feeds;
+mod filters;
+mod health;
+mod link_previews;
+mod polls;
 mod posts;
 mod profiles;
+mod relationships;
 mod users;
+mod visibility;
+
+// Keeps the JSON error format for bodies and query strings that fail to parse.
+fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            ServiceError::PayloadTooLarge("payload_too_large", message!("payload-too-large"))
+        }
+        err => ServiceError::BadRequest("invalid_json", message!("invalid-json", error = err)),
+    }
+    .into()
+}
+
+fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    ServiceError::BadRequest("invalid_query", message!("invalid-query", error = err)).into()
+}
+
+fn multipart_error(err: MultipartError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        MultipartError::Payload(PayloadError::Overflow) => {
+            ServiceError::PayloadTooLarge("payload_too_large", message!("payload-too-large"))
+        }
+        err => ServiceError::BadRequest(
+            "invalid_multipart",
+            message!("invalid-multipart", error = err),
+        ),
+    }
+    .into()
+}
+
+#[derive(Parser)]
+#[command(version, about = "Runs the microblogs API server")]
+struct Cli {
+    /// Path to the TOML configuration file [default: microblogs.toml, if present]
+    #[arg(short, long, env = "MICROBLOGS_CONFIG")]
+    config: Option<PathBuf>,
+    /// Start without applying pending migrations
+    #[arg(long, env = "NO_MIGRATE")]
+    no_migrate: bool,
+    #[command(flatten)]
+    overrides: ConfigOverrides,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// List the migrations and whether they were applied
+    Status,
+    /// Apply the pending migrations
+    Up,
+    /// Revert the latest applied migrations
+    Down {
+        /// Number of migrations to revert
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+}
+
+// Startup errors are reported like the ones from clap instead of panicking.
+fn exit_with(reason: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", reason);
+    process::exit(1)
+}
+
+fn migrate(action: MigrateAction, pool: &DbPool) {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(err) => exit_with(format!("could not connect to the database: {}", err)),
+    };
+
+    let result = match action {
+        MigrateAction::Status => migrations::status(&mut conn).map(|migrations| {
+            for migration in migrations {
+                let mark = if migration.applied { "X" } else { " " };
+                println!("[{}] {}", mark, migration.name);
+            }
+        }),
+        MigrateAction::Up => migrations::run_pending(&mut conn).map(|applied| {
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for version in applied {
+                println!("Applied {}", version);
+            }
+        }),
+        MigrateAction::Down { steps } => migrations::revert(&mut conn, steps).map(|reverted| {
+            for version in reverted {
+                println!("Reverted {}", version);
+            }
+        }),
+    };
+
+    if let Err(err) = result {
+        exit_with(format!("migration failed: {}", err));
+    }
+}
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
@@ -20,44 +148,127 @@ async fn main() -> Result<(), std::io::Error> {
     dotenv().ok();
     env_logger::init_from_env(Env::default().default_filter_or("info"));
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = r2d2::ConnectionManager::<SqliteConnection>::new(database_url);
-    let pool = r2d2::Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool.");
+    let cli = Cli::parse();
+    let config = match Config::load(cli.config.as_deref(), cli.overrides) {
+        Ok(config) => config,
+        Err(err) => exit_with(err),
+    };
+
+    let pool = match build_pool(&config.database.url, config.database.pool_size) {
+        Ok(pool) => pool,
+        Err(err) => exit_with(format!(
+            "could not connect to {}: {}",
+            config.database.url, err
+        )),
+    };
+
+    if let Some(Command::Migrate { action }) = cli.command {
+        migrate(action, &pool);
+        return Ok(());
+    }
+
+    if let Err(err) = config.check_server() {
+        exit_with(err);
+    }
 
-    let uploads_dir = env::var("UPLOADS_DIR").expect("UPLOADS_DIR must be set");
-    let uploads_dir_path = Path::new(&uploads_dir);
-    if !uploads_dir_path.exists() {
-        create_dir(uploads_dir_path).unwrap();
+    if !cli.no_migrate {
+        let applied = pool
+            .get()
+            .map_err(|err| err.to_string())
+            .and_then(|mut conn| migrations::run_pending(&mut conn).map_err(|err| err.to_string()));
+        match applied {
+            Ok(applied) => {
+                for version in applied {
+                    log::info!("Applied migration {}", version);
+                }
+            }
+            Err(err) => exit_with(format!("could not apply the migrations: {}", err)),
+        }
     }
 
-    HttpServer::new(move || {
+    let write_pool = match build_write_pool(&config.database.url, &pool) {
+        Ok(write_pool) => write_pool,
+        Err(err) => exit_with(format!(
+            "could not connect to {}: {}",
+            config.database.url, err
+        )),
+    };
+
+    if let Err(err) = create_dir_all(&config.uploads.dir) {
+        exit_with(format!(
+            "could not create the uploads directory {}: {}",
+            config.uploads.dir, err
+        ));
+    }
+
+    let link_previews = LinkPreviewConfig {
+        enabled: config.features.link_previews,
+        timeout: Duration::from_secs(config.link_previews.timeout_secs),
+        max_page_size: config.link_previews.max_page_size,
+        user_agent: format!("microblogs/{}", env!("CARGO_PKG_VERSION")),
+        // lets a local stub server stand in for real sites during development
+        allow_private_hosts: config.link_previews.allow_private_hosts,
+    };
+
+    let hub = EventHub::new();
+    actix_web::rt::spawn(drafts::run_scheduler(write_pool.clone(), hub.clone()));
+
+    let bind = config.server.bind.clone();
+    let workers = config.server.workers;
+    let mut server = HttpServer::new(move || {
+        let cors = config
+            .cors
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allow_any_method()
+            .allow_any_header()
+            .max_age(3600);
+
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(write_pool.clone()))
+            .app_data(web::Data::new(hub.clone()))
             .app_data(web::Data::new(AppState {
-                secret_key: std::env::var("SECRET_KEY").expect("SECRET_KEY must be set"),
-                uploads_dir: uploads_dir.clone(),
+                secret_key: config.auth.secret_key.clone(),
+                token_lifetime: chrono::Duration::hours(config.auth.token_lifetime_hours),
+                registrations_open: config.features.registrations,
+                uploads_dir: config.uploads.dir.clone(),
+                max_upload_files: config.uploads.max_files,
+                reaction_emojis: config.posts.reactions.clone(),
+                max_pinned_posts: config.posts.max_pinned,
+                max_post_length: config.posts.max_length,
+                link_previews: link_previews.clone(),
             }))
-            .wrap(Logger::default())
-            .wrap(
-                Cors::default()
-                    .allowed_origin(
-                        std::env::var("FRONTEND_ORIGIN")
-                            .expect("FRONTEND_ORIGIN must be set")
-                            .as_str(),
-                    )
-                    .allow_any_method()
-                    .allow_any_header()
-                    .max_age(3600),
+            .app_data(web::JsonConfig::default().error_handler(json_error))
+            .app_data(web::QueryConfig::default().error_handler(query_error))
+            .app_data(
+                MultipartFormConfig::default()
+                    .total_limit(config.uploads.max_size)
+                    .error_handler(multipart_error),
             )
+            .wrap(Localize)
+            .wrap(RequestId)
+            .wrap(Logger::new(
+                "%a \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\" %T %{x-request-id}o",
+            ))
+            .wrap(cors)
             .configure(users::configure)
             .configure(profiles::configure)
+            .configure(relationships::configure)
             .configure(attachments::configure)
+            .configure(bookmarks::configure)
+            .configure(drafts::configure)
             .configure(posts::configure)
             .configure(feeds::configure)
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+            .configure(filters::configure)
+            .configure(polls::configure)
+            .configure(events::configure)
+            .configure(health::configure)
+    });
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+
+    server.bind(bind)?.run().await
 }
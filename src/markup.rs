@@ -0,0 +1,459 @@
+use serde::Serialize;
+
+const URL_SCHEMES: [&str; 2] = ["https://", "http://"];
+const URL_TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FacetKind {
+    Bold,
+    Italic,
+    Strikethrough,
+    Code,
+    Link,
+    Mention,
+    Hashtag,
+}
+
+// A formatted or linked span of a post body. `start` and `end` are byte offsets
+// into the raw body, including any Markdown markers around the span.
+#[derive(Serialize, Clone)]
+pub struct Facet {
+    pub kind: FacetKind,
+    pub start: usize,
+    pub end: usize,
+    pub value: Option<String>,
+}
+
+pub struct Markup {
+    pub html: String,
+    pub facets: Vec<Facet>,
+}
+
+// Renders a post body into HTML, supporting bold, italic, strikethrough and
+// inline code, plus autolinked URLs, mentions and hashtags. Everything else is
+// escaped, so the output is safe to insert into a page as is.
+pub fn render(body: &str) -> Markup {
+    let mut renderer = Renderer {
+        html: String::with_capacity(body.len()),
+        facets: Vec::new(),
+    };
+
+    let mut start = 0;
+    for (index, separator) in paragraph_breaks(body) {
+        renderer.paragraph(&body[start..index], start);
+        start = index + separator;
+    }
+    renderer.paragraph(&body[start..], start);
+    renderer.facets.sort_by_key(|facet| facet.start);
+
+    Markup {
+        html: renderer.html,
+        facets: renderer.facets,
+    }
+}
+
+// Blank lines separate paragraphs. Returns the offset and length of each break.
+fn paragraph_breaks(body: &str) -> Vec<(usize, usize)> {
+    let mut breaks = Vec::new();
+    let mut rest = 0;
+    while let Some(found) = body[rest..].find("\n\n") {
+        let index = rest + found;
+        let separator = body[index..].len() - body[index..].trim_start_matches('\n').len();
+        breaks.push((index, separator));
+        rest = index + separator;
+    }
+    breaks
+}
+
+struct Renderer {
+    html: String,
+    facets: Vec<Facet>,
+}
+
+impl Renderer {
+    fn paragraph(&mut self, text: &str, base: usize) {
+        if text.trim().is_empty() {
+            return;
+        }
+        self.html.push_str("<p>");
+        self.inline(text, base);
+        self.html.push_str("</p>");
+    }
+
+    fn inline(&mut self, text: &str, base: usize) {
+        let mut index = 0;
+        while let Some(c) = text[index..].chars().next() {
+            let rest = &text[index..];
+            let at_boundary = text[..index].chars().next_back().is_none_or(|previous| {
+                !is_word_char(previous) && previous != '@' && previous != '#'
+            });
+
+            if c == '\\' {
+                if let Some(escaped) = rest[1..]
+                    .chars()
+                    .next()
+                    .filter(|e| e.is_ascii_punctuation())
+                {
+                    escape_char(&mut self.html, escaped);
+                    index += 1 + escaped.len_utf8();
+                    continue;
+                }
+            }
+
+            if c == '`' {
+                if let Some(end) = closing(rest, "`") {
+                    self.html.push_str("<code>");
+                    escape_into(&mut self.html, &rest[1..end]);
+                    self.html.push_str("</code>");
+                    self.push_facet(FacetKind::Code, base + index, base + index + end + 1, None);
+                    index += end + 1;
+                    continue;
+                }
+            }
+
+            if let Some((delimiter, tag, kind)) = emphasis(rest, at_boundary) {
+                if let Some(end) = closing(rest, delimiter) {
+                    let inner_start = delimiter.len();
+                    self.html.push_str(&format!("<{}>", tag));
+                    self.inline(&rest[inner_start..end], base + index + inner_start);
+                    self.html.push_str(&format!("</{}>", tag));
+                    let span_end = end + delimiter.len();
+                    self.push_facet(kind, base + index, base + index + span_end, None);
+                    index += span_end;
+                    continue;
+                }
+            }
+
+            if at_boundary {
+                if let Some(length) = url_length(rest) {
+                    let url = &rest[..length];
+                    self.html.push_str("<a href=\"");
+                    escape_into(&mut self.html, url);
+                    self.html
+                        .push_str("\" rel=\"nofollow noopener noreferrer\" target=\"_blank\">");
+                    escape_into(&mut self.html, url);
+                    self.html.push_str("</a>");
+                    self.push_facet(
+                        FacetKind::Link,
+                        base + index,
+                        base + index + length,
+                        Some(url.to_string()),
+                    );
+                    index += length;
+                    continue;
+                }
+
+                if c == '@' || c == '#' {
+                    let name_length = rest[1..]
+                        .find(|c: char| !is_word_char(c))
+                        .unwrap_or(rest.len() - 1);
+                    let name = &rest[1..1 + name_length];
+                    if !name.is_empty() {
+                        if c == '@' {
+                            self.html.push_str("<a href=\"/perfil/");
+                            escape_into(&mut self.html, name);
+                            self.html.push_str("\" class=\"mention\">@");
+                            escape_into(&mut self.html, name);
+                            self.html.push_str("</a>");
+                        } else {
+                            self.html.push_str("<span class=\"hashtag\">#");
+                            escape_into(&mut self.html, name);
+                            self.html.push_str("</span>");
+                        }
+                        let kind = if c == '@' {
+                            FacetKind::Mention
+                        } else {
+                            FacetKind::Hashtag
+                        };
+                        self.push_facet(
+                            kind,
+                            base + index,
+                            base + index + 1 + name_length,
+                            Some(name.to_string()),
+                        );
+                        index += 1 + name_length;
+                        continue;
+                    }
+                }
+            }
+
+            if c == '\n' {
+                self.html.push_str("<br>");
+            } else {
+                escape_char(&mut self.html, c);
+            }
+            index += c.len_utf8();
+        }
+    }
+
+    fn push_facet(&mut self, kind: FacetKind, start: usize, end: usize, value: Option<String>) {
+        self.facets.push(Facet {
+            kind,
+            start,
+            end,
+            value,
+        });
+    }
+}
+
+// The emphasis opened at the start of `text`, if any. Underscores only count at
+// the start of a word, so names like snake_case stay as they are.
+fn emphasis(text: &str, at_boundary: bool) -> Option<(&'static str, &'static str, FacetKind)> {
+    if text.starts_with("**") {
+        Some(("**", "strong", FacetKind::Bold))
+    } else if text.starts_with("~~") {
+        Some(("~~", "del", FacetKind::Strikethrough))
+    } else if text.starts_with('*') {
+        Some(("*", "em", FacetKind::Italic))
+    } else if text.starts_with('_') && at_boundary {
+        Some(("_", "em", FacetKind::Italic))
+    } else {
+        None
+    }
+}
+
+// Finds the offset of the delimiter closing the one at the start of `text`. The
+// span must not be empty, start or end with whitespace, or cross a line break.
+// A run of markers closes with its last ones, so `***` ends both `*` and `**`.
+fn closing(text: &str, delimiter: &str) -> Option<usize> {
+    let inner_start = delimiter.len();
+    let line_end = text.find('\n').unwrap_or(text.len());
+    let marker = delimiter.chars().next()?;
+    let mut search = inner_start;
+    loop {
+        let found = search + text[search..line_end].find(delimiter)?;
+        let run_end = line_end - text[found..line_end].trim_start_matches(marker).len();
+        search = run_end;
+
+        // markers after whitespace open a span rather than close one
+        if text[..found].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let end = run_end - delimiter.len();
+        let inner = &text[inner_start..end];
+        if inner.is_empty() || inner.trim() != inner {
+            return None;
+        }
+        if delimiter == "_" && text[run_end..].chars().next().is_some_and(is_word_char) {
+            continue;
+        }
+        return Some(end);
+    }
+}
+
+// Length of the URL at the start of `text`, leaving out trailing punctuation that
+// most likely belongs to the sentence. A closing parenthesis is kept when it
+// matches one opened in the URL, as in Wikipedia links.
+fn url_length(text: &str) -> Option<usize> {
+    let scheme = URL_SCHEMES.iter().find(|scheme| {
+        text.get(..scheme.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    })?;
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    let mut url = &text[..end];
+    while let Some(last) = url.chars().next_back() {
+        let matched_paren = last == ')' && url.matches('(').count() >= url.matches(')').count();
+        if !URL_TRAILING_PUNCTUATION.contains(&last) || matched_paren {
+            break;
+        }
+        url = &url[..url.len() - last.len_utf8()];
+    }
+    if url.len() <= scheme.len() {
+        return None;
+    }
+    Some(url.len())
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn escape_into(html: &mut String, text: &str) {
+    for c in text.chars() {
+        escape_char(html, c);
+    }
+}
+
+fn escape_char(html: &mut String, c: char) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        _ => html.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facets(body: &str) -> Vec<(FacetKind, usize, usize, Option<String>)> {
+        render(body)
+            .facets
+            .into_iter()
+            .map(|facet| (facet.kind, facet.start, facet.end, facet.value))
+            .collect()
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            render("<script>alert(\"x\") & 'y'</script>").html,
+            "<p>&lt;script&gt;alert(&quot;x&quot;) &amp; &#39;y&#39;&lt;/script&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn escapes_inside_code_and_emphasis() {
+        assert_eq!(render("`<b>`").html, "<p><code>&lt;b&gt;</code></p>");
+        assert_eq!(render("**<i>**").html, "<p><strong>&lt;i&gt;</strong></p>");
+    }
+
+    #[test]
+    fn renders_nested_emphasis() {
+        assert_eq!(
+            render("**bold *and italic***").html,
+            "<p><strong>bold <em>and italic</em></strong></p>"
+        );
+        assert_eq!(
+            render("_a **b** c_").html,
+            "<p><em>a <strong>b</strong> c</em></p>"
+        );
+    }
+
+    #[test]
+    fn closes_emphasis_with_the_end_of_a_marker_run() {
+        assert_eq!(
+            render("*italic **bold***").html,
+            "<p><em>italic <strong>bold</strong></em></p>"
+        );
+        assert_eq!(
+            render("**a *b** c*").html,
+            "<p><strong>a *b</strong> c*</p>"
+        );
+    }
+
+    #[test]
+    fn leaves_unterminated_emphasis() {
+        assert_eq!(render("*open").html, "<p>*open</p>");
+        assert_eq!(render("**open").html, "<p>**open</p>");
+        assert_eq!(render("_open").html, "<p>_open</p>");
+        assert_eq!(render("* not *").html, "<p>* not *</p>");
+        assert_eq!(render("*across\nlines*").html, "<p>*across<br>lines*</p>");
+    }
+
+    #[test]
+    fn ignores_underscores_inside_words() {
+        assert_eq!(render("snake_case_name").html, "<p>snake_case_name</p>");
+        assert_eq!(render("_word_s").html, "<p>_word_s</p>");
+    }
+
+    #[test]
+    fn leaves_trailing_punctuation_out_of_urls() {
+        assert_eq!(
+            facets("see https://example.com/a.")[0],
+            (
+                FacetKind::Link,
+                4,
+                25,
+                Some("https://example.com/a".to_string())
+            )
+        );
+        let body = "(https://example.com/a), \"https://example.com/b\"!";
+        let links: Vec<Option<String>> = facets(body)
+            .into_iter()
+            .map(|(_, _, _, value)| value)
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                Some("https://example.com/a".to_string()),
+                Some("https://example.com/b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_matched_parentheses_in_urls() {
+        let body = "(see https://en.wikipedia.org/wiki/Rust_(programming_language))";
+        assert_eq!(
+            facets(body)[0].3.as_deref(),
+            Some("https://en.wikipedia.org/wiki/Rust_(programming_language)")
+        );
+    }
+
+    #[test]
+    fn escapes_urls() {
+        assert_eq!(
+            render("https://example.com/?a=1&b=\"2\"").html,
+            "<p><a href=\"https://example.com/?a=1&amp;b=&quot;2\" \
+             rel=\"nofollow noopener noreferrer\" target=\"_blank\">\
+             https://example.com/?a=1&amp;b=&quot;2</a>&quot;</p>"
+        );
+    }
+
+    #[test]
+    fn does_not_link_other_schemes() {
+        let markup = render("javascript:alert(1) data:text/html,<b>x</b> jAvAsCrIpT:x");
+        assert!(markup.facets.is_empty());
+        assert!(!markup.html.contains("<a"));
+        assert!(!markup.html.contains("<b>"));
+    }
+
+    #[test]
+    fn links_mentions_and_hashtags_at_word_boundaries() {
+        assert_eq!(
+            facets("@ana #rust"),
+            vec![
+                (FacetKind::Mention, 0, 4, Some("ana".to_string())),
+                (FacetKind::Hashtag, 5, 10, Some("rust".to_string())),
+            ]
+        );
+        assert!(facets("mail@example.com issue#12").is_empty());
+        assert_eq!(
+            facets("(@ana)")[0],
+            (FacetKind::Mention, 1, 5, Some("ana".to_string()))
+        );
+        assert!(facets("@ # @!").is_empty());
+    }
+
+    #[test]
+    fn supports_non_ascii_names() {
+        assert_eq!(
+            facets("@joão #café"),
+            vec![
+                (FacetKind::Mention, 0, 6, Some("joão".to_string())),
+                (FacetKind::Hashtag, 7, 13, Some("café".to_string())),
+            ]
+        );
+        assert!(facets("olá@joão é#café").is_empty());
+    }
+
+    #[test]
+    fn reports_byte_offsets_on_multibyte_text() {
+        let body = "ção **negrito** 🎉 `código` @zé";
+        let found = facets(body);
+        assert_eq!(
+            found,
+            vec![
+                (FacetKind::Bold, 6, 17, None),
+                (FacetKind::Code, 23, 32, None),
+                (FacetKind::Mention, 33, 37, Some("zé".to_string())),
+            ]
+        );
+        assert_eq!(&body[6..17], "**negrito**");
+        assert_eq!(&body[23..32], "`código`");
+        assert_eq!(&body[33..37], "@zé");
+    }
+
+    #[test]
+    fn offsets_count_from_the_start_of_the_body_across_paragraphs() {
+        let body = "é\n\n\n*x*";
+        assert_eq!(render(body).html, "<p>é</p><p><em>x</em></p>");
+        assert_eq!(facets(body), vec![(FacetKind::Italic, 5, 8, None)]);
+        assert_eq!(&body[5..8], "*x*");
+    }
+}
@@ -0,0 +1,79 @@
+use diesel::migration::{MigrationSource, MigrationVersion, Result};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use crate::{DbBackend, DbConn};
+
+// The migrations of the enabled backend, built into the binary so deploying
+// doesn't need diesel_cli or the sources. Both directories must describe the
+// same schema.
+#[cfg(feature = "sqlite")]
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
+#[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgres");
+
+pub struct MigrationStatus {
+    pub name: String,
+    pub applied: bool,
+}
+
+// Applies every pending migration, returning their versions.
+pub fn run_pending(conn: &mut DbConn) -> Result<Vec<String>> {
+    let applied = conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(applied.iter().map(MigrationVersion::to_string).collect())
+}
+
+// Reverts up to `steps` of the latest applied migrations, returning their
+// versions.
+pub fn revert(conn: &mut DbConn, steps: usize) -> Result<Vec<String>> {
+    let mut reverted = Vec::new();
+    for _ in 0..steps {
+        if conn.applied_migrations()?.is_empty() {
+            break;
+        }
+        reverted.push(conn.revert_last_migration(MIGRATIONS)?.to_string());
+    }
+    Ok(reverted)
+}
+
+// Every embedded migration, oldest first, with whether it was applied.
+pub fn status(conn: &mut DbConn) -> Result<Vec<MigrationStatus>> {
+    let applied = conn.applied_migrations()?;
+    let mut migrations = MigrationSource::<DbBackend>::migrations(&MIGRATIONS)?;
+    migrations.sort_by_key(|migration| migration.name().version().as_owned());
+
+    Ok(migrations
+        .iter()
+        .map(|migration| MigrationStatus {
+            name: migration.name().to_string(),
+            applied: applied.contains(&migration.name().version()),
+        })
+        .collect())
+}
+
+pub fn is_current(conn: &mut DbConn) -> Result<bool> {
+    Ok(!conn.has_pending_migration(MIGRATIONS)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_connection;
+
+    #[test]
+    fn migrations_revert_and_reapply() {
+        let mut conn = test_connection();
+        let migration_count = status(&mut conn).unwrap().len();
+        assert!(is_current(&mut conn).unwrap());
+
+        let reverted = revert(&mut conn, usize::MAX).unwrap();
+        assert_eq!(reverted.len(), migration_count);
+        assert!(status(&mut conn)
+            .unwrap()
+            .iter()
+            .all(|migration| !migration.applied));
+
+        let applied = run_pending(&mut conn).unwrap();
+        assert_eq!(applied.len(), migration_count);
+        assert!(is_current(&mut conn).unwrap());
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{
+    post,
+    web::{self, ServiceConfig},
+    Error, HttpResponse,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, OptionalExtension, QueryDsl,
+    Queryable, RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{errors::ServiceError, message, schema, DbConn, WritePool};
+use serde::{Deserialize, Serialize};
+
+use crate::{posts::Post, users::UserDetails, visibility::visible_to};
+
+const MIN_OPTIONS: usize = 2;
+const MAX_OPTIONS: usize = 4;
+const MAX_LABEL_LENGTH: usize = 64;
+const MIN_DURATION_SECONDS: i64 = 5 * 60;
+const MAX_DURATION_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct PollCreate {
+    options: Vec<String>,
+    #[serde(default)]
+    multiple_choice: bool,
+    expires_in: i64,
+}
+
+impl PollCreate {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.options.len() < MIN_OPTIONS || self.options.len() > MAX_OPTIONS {
+            return Err(ServiceError::BadRequest(
+                "invalid_poll_options",
+                message!(
+                    "invalid-poll-option-count",
+                    min = MIN_OPTIONS,
+                    max = MAX_OPTIONS
+                ),
+            ));
+        }
+
+        for option in &self.options {
+            let label = option.trim();
+            if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+                return Err(ServiceError::BadRequest(
+                    "invalid_poll_options",
+                    message!("invalid-poll-option-length", max = MAX_LABEL_LENGTH),
+                ));
+            }
+        }
+
+        if self.expires_in < MIN_DURATION_SECONDS || self.expires_in > MAX_DURATION_SECONDS {
+            return Err(ServiceError::BadRequest(
+                "invalid_poll_duration",
+                message!(
+                    "invalid-poll-duration",
+                    min_minutes = MIN_DURATION_SECONDS / 60,
+                    max_days = MAX_DURATION_SECONDS / (24 * 60 * 60)
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct PollVote {
+    choices: Vec<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::polls)]
+struct NewPoll {
+    pub post_id: i32,
+    pub multiple_choice: bool,
+    pub closes_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::poll_options)]
+struct NewPollOption<'a> {
+    pub poll_id: i32,
+    pub position: i32,
+    pub label: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::poll_votes)]
+struct NewPollVote {
+    pub poll_id: i32,
+    pub option_id: i32,
+    pub user_id: i32,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::polls)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct Poll {
+    pub id: i32,
+    pub post_id: i32,
+    pub multiple_choice: bool,
+    pub closes_at: NaiveDateTime,
+}
+
+impl Poll {
+    fn is_closed(&self) -> bool {
+        self.closes_at <= Utc::now().naive_utc()
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::poll_options)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct PollOption {
+    pub id: i32,
+    pub poll_id: i32,
+    pub position: i32,
+    pub label: String,
+    pub vote_count: i32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PollOptionRead {
+    position: i32,
+    label: String,
+    votes: i32,
+    voted_by_user: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PollRead {
+    options: Vec<PollOptionRead>,
+    multiple_choice: bool,
+    closes_at: String,
+    closed: bool,
+    voted_by_user: bool,
+}
+
+pub fn create_poll(
+    target_post_id: i32,
+    poll: &PollCreate,
+    conn: &mut DbConn,
+) -> Result<(), diesel::result::Error> {
+    use schema::poll_options::dsl::poll_options;
+    use schema::polls::dsl::polls;
+
+    let new_poll = NewPoll {
+        post_id: target_post_id,
+        multiple_choice: poll.multiple_choice,
+        closes_at: Utc::now().naive_utc() + Duration::seconds(poll.expires_in),
+    };
+
+    let created = diesel::insert_into(polls)
+        .values(&new_poll)
+        .returning(Poll::as_returning())
+        .get_result(conn)?;
+
+    let new_options: Vec<NewPollOption> = poll
+        .options
+        .iter()
+        .enumerate()
+        .map(|(index, label)| NewPollOption {
+            poll_id: created.id,
+            position: index as i32,
+            label: label.trim(),
+        })
+        .collect();
+
+    diesel::insert_into(poll_options)
+        .values(&new_options)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+// Moves the closing time of a post's poll forward, used when a draft is published.
+pub fn postpone_poll(
+    target_post_id: i32,
+    delay: Duration,
+    conn: &mut DbConn,
+) -> Result<(), diesel::result::Error> {
+    use schema::polls::dsl::{closes_at, polls, post_id};
+
+    let found: Vec<Poll> = polls
+        .filter(post_id.eq(target_post_id))
+        .select(Poll::as_select())
+        .load(conn)?;
+
+    for poll in found {
+        diesel::update(polls)
+            .filter(schema::polls::id.eq(poll.id))
+            .set(closes_at.eq(poll.closes_at + delay))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+// Loads the polls attached to the given posts, keyed by post id.
+pub fn load_polls(
+    post_ids: &[i32],
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<HashMap<i32, PollRead>, diesel::result::Error> {
+    use schema::poll_options::dsl::{poll_id as option_poll_id, poll_options, position};
+    use schema::poll_votes::dsl::{
+        option_id, poll_id as vote_poll_id, poll_votes, user_id as vote_user_id,
+    };
+    use schema::polls::dsl::{polls, post_id};
+
+    let found: Vec<Poll> = polls
+        .filter(post_id.eq_any(post_ids))
+        .select(Poll::as_select())
+        .load(conn)?;
+    if found.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let poll_ids: Vec<i32> = found.iter().map(|poll| poll.id).collect();
+    let options: Vec<PollOption> = poll_options
+        .filter(option_poll_id.eq_any(&poll_ids))
+        .select(PollOption::as_select())
+        .order_by(position.asc())
+        .load(conn)?;
+    let own_votes: HashSet<i32> = poll_votes
+        .filter(
+            vote_user_id
+                .eq(current_user_id)
+                .and(vote_poll_id.eq_any(&poll_ids)),
+        )
+        .select(option_id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect();
+
+    let mut options_by_poll: HashMap<i32, Vec<PollOptionRead>> = HashMap::new();
+    for option in options {
+        options_by_poll
+            .entry(option.poll_id)
+            .or_default()
+            .push(PollOptionRead {
+                position: option.position,
+                label: option.label,
+                votes: option.vote_count,
+                voted_by_user: own_votes.contains(&option.id),
+            });
+    }
+
+    Ok(found
+        .into_iter()
+        .map(|poll| {
+            let options = options_by_poll.remove(&poll.id).unwrap_or_default();
+            let read = PollRead {
+                voted_by_user: options.iter().any(|option| option.voted_by_user),
+                options,
+                multiple_choice: poll.multiple_choice,
+                closes_at: poll.closes_at.to_string(),
+                closed: poll.is_closed(),
+            };
+            (poll.post_id, read)
+        })
+        .collect())
+}
+
+// Registers the user's choices on an open poll and returns the updated poll.
+fn cast_vote(
+    target_post_id: i32,
+    poll: &Poll,
+    choices: &[i32],
+    current_user_id: i32,
+    target_post_uuid: &str,
+    conn: &mut DbConn,
+) -> Result<PollRead, ServiceError> {
+    use schema::poll_options::dsl::{
+        id as option_id, poll_id as option_poll_id, poll_options, vote_count,
+    };
+    use schema::poll_votes::dsl::{poll_id as vote_poll_id, poll_votes, user_id as vote_user_id};
+    use schema::polls::dsl::{id as poll_id, multiple_choice, polls};
+
+    if poll.is_closed() {
+        return Err(ServiceError::Conflict(
+            "poll_closed",
+            message!("poll-closed", uuid = target_post_uuid),
+        ));
+    }
+
+    let mut choices = choices.to_vec();
+    choices.sort();
+    choices.dedup();
+    if choices.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "poll_choice_missing",
+            message!("poll-choice-missing"),
+        ));
+    }
+    if !poll.multiple_choice && choices.len() > 1 {
+        return Err(ServiceError::BadRequest(
+            "too_many_poll_choices",
+            message!("too-many-poll-choices"),
+        ));
+    }
+
+    // `false` when the user already voted on the poll
+    let result = conn.transaction::<bool, diesel::result::Error, _>(|conn| {
+        // a no-op update that locks the poll row, so concurrent votes of the
+        // same user can't both pass the check below
+        diesel::update(polls)
+            .filter(poll_id.eq(poll.id))
+            .set(multiple_choice.eq(multiple_choice))
+            .execute(conn)?;
+
+        if poll_votes
+            .filter(
+                vote_poll_id
+                    .eq(poll.id)
+                    .and(vote_user_id.eq(current_user_id)),
+            )
+            .select(schema::poll_votes::id)
+            .first::<i32>(conn)
+            .optional()?
+            .is_some()
+        {
+            return Ok(false);
+        }
+
+        let options: Vec<PollOption> = poll_options
+            .filter(option_poll_id.eq(poll.id))
+            .select(PollOption::as_select())
+            .load(conn)?;
+
+        for choice in &choices {
+            let option = match options.iter().find(|option| option.position == *choice) {
+                Some(option) => option,
+                None => return Err(diesel::result::Error::NotFound),
+            };
+
+            diesel::insert_into(poll_votes)
+                .values(&NewPollVote {
+                    poll_id: poll.id,
+                    option_id: option.id,
+                    user_id: current_user_id,
+                })
+                .execute(conn)?;
+
+            diesel::update(poll_options)
+                .filter(option_id.eq(option.id))
+                .set(vote_count.eq(vote_count + 1))
+                .execute(conn)?;
+        }
+
+        Ok(true)
+    });
+
+    match result {
+        Ok(true) => {}
+        Ok(false)
+        | Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        )) => {
+            return Err(ServiceError::Conflict(
+                "already_voted",
+                message!("already-voted", uuid = target_post_uuid),
+            ))
+        }
+        // one of the choices is not an option of the poll
+        Err(diesel::result::Error::NotFound) => {
+            return Err(ServiceError::BadRequest(
+                "invalid_poll_choice",
+                message!("invalid-poll-choice", uuid = target_post_uuid),
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    match load_polls(&[target_post_id], current_user_id, conn) {
+        Ok(mut loaded) => match loaded.remove(&target_post_id) {
+            Some(poll) => Ok(poll),
+            None => Err(ServiceError::InternalServerError(
+                "internal_error",
+                message!("poll-not-loaded", uuid = target_post_uuid),
+            )),
+        },
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[post("/{target_post_uuid}/vote")]
+async fn vote_poll(
+    target_post_uuid: web::Path<String>,
+    info: web::Json<PollVote>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::polls::dsl::polls;
+    use schema::posts::dsl::{
+        deleted as post_deleted, posts, published as post_published, uuid as post_uuid,
+    };
+
+    let poll = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let (post, poll) = match posts
+            .inner_join(polls)
+            .filter(
+                post_uuid
+                    .eq(target_post_uuid.as_str())
+                    .and(post_deleted.eq(false))
+                    .and(post_published.eq(true))
+                    .and(visible_to(current_user.id)),
+            )
+            .select((Post::as_select(), Poll::as_select()))
+            .first::<(Post, Poll)>(&mut conn)
+        {
+            Ok(result) => result,
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::NotFound(
+                    "poll_not_found",
+                    message!("poll-not-found", uuid = target_post_uuid),
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        cast_vote(
+            post.id,
+            &poll,
+            &info.choices,
+            current_user.id,
+            &target_post_uuid,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(poll))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(web::scope("/polls").service(vote_poll));
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use microblogs::test_connection;
+
+    use super::*;
+
+    fn poll_create(option_count: usize, expires_in: i64) -> PollCreate {
+        PollCreate {
+            options: (0..option_count)
+                .map(|index| format!("option {}", index))
+                .collect(),
+            multiple_choice: false,
+            expires_in,
+        }
+    }
+
+    // Poll 1 is a single-choice poll that is still open, poll 2 allows
+    // several choices and poll 3 already closed.
+    fn seed_polls(conn: &mut DbConn) {
+        conn.batch_execute(
+            "INSERT INTO users (id, username, email, real_name, summary, password) VALUES
+                 (1, 'a', 'a@example.com', 'A', '', 'x'),
+                 (2, 'b', 'b@example.com', 'B', '', 'x');
+             INSERT INTO posts (id, uuid, parent_id, poster_id, body) VALUES
+                 (1, 'single', NULL, 1, 'single'),
+                 (2, 'multiple', NULL, 1, 'multiple'),
+                 (3, 'closed', NULL, 1, 'closed');
+             INSERT INTO polls (id, post_id, multiple_choice, closes_at) VALUES
+                 (1, 1, FALSE, '2999-01-01 00:00:00'),
+                 (2, 2, TRUE, '2999-01-01 00:00:00'),
+                 (3, 3, FALSE, '2000-01-01 00:00:00');
+             INSERT INTO poll_options (poll_id, position, label) VALUES
+                 (1, 0, 'yes'), (1, 1, 'no'),
+                 (2, 0, 'red'), (2, 1, 'green'), (2, 2, 'blue'),
+                 (3, 0, 'yes'), (3, 1, 'no');",
+        )
+        .unwrap();
+    }
+
+    fn vote(
+        target_post_id: i32,
+        choices: &[i32],
+        conn: &mut DbConn,
+    ) -> Result<PollRead, ServiceError> {
+        let poll = schema::polls::table
+            .filter(schema::polls::post_id.eq(target_post_id))
+            .select(Poll::as_select())
+            .first(conn)
+            .unwrap();
+        cast_vote(target_post_id, &poll, choices, 2, "uuid", conn)
+    }
+
+    fn error_code(result: Result<PollRead, ServiceError>) -> &'static str {
+        match result {
+            Ok(_) => "ok",
+            Err(err) => err.code(),
+        }
+    }
+
+    #[test]
+    fn validate_limits_option_count() {
+        assert!(poll_create(MIN_OPTIONS, MIN_DURATION_SECONDS)
+            .validate()
+            .is_ok());
+        assert!(poll_create(MAX_OPTIONS, MIN_DURATION_SECONDS)
+            .validate()
+            .is_ok());
+        for count in [MIN_OPTIONS - 1, MAX_OPTIONS + 1] {
+            let err = poll_create(count, MIN_DURATION_SECONDS)
+                .validate()
+                .unwrap_err();
+            assert_eq!(err.code(), "invalid_poll_options");
+        }
+    }
+
+    #[test]
+    fn validate_limits_option_labels() {
+        let mut poll = poll_create(MIN_OPTIONS, MIN_DURATION_SECONDS);
+        poll.options[0] = "   ".to_string();
+        assert_eq!(poll.validate().unwrap_err().code(), "invalid_poll_options");
+
+        poll.options[0] = "x".repeat(MAX_LABEL_LENGTH + 1);
+        assert_eq!(poll.validate().unwrap_err().code(), "invalid_poll_options");
+    }
+
+    #[test]
+    fn validate_limits_duration() {
+        assert!(poll_create(MIN_OPTIONS, MAX_DURATION_SECONDS)
+            .validate()
+            .is_ok());
+        for expires_in in [MIN_DURATION_SECONDS - 1, MAX_DURATION_SECONDS + 1] {
+            let err = poll_create(MIN_OPTIONS, expires_in).validate().unwrap_err();
+            assert_eq!(err.code(), "invalid_poll_duration");
+        }
+    }
+
+    #[test]
+    fn voting_on_a_closed_poll_is_rejected() {
+        let mut conn = test_connection();
+        seed_polls(&mut conn);
+
+        assert_eq!(error_code(vote(3, &[0], &mut conn)), "poll_closed");
+    }
+
+    #[test]
+    fn single_choice_polls_take_one_vote() {
+        let mut conn = test_connection();
+        seed_polls(&mut conn);
+
+        assert_eq!(
+            error_code(vote(1, &[0, 1], &mut conn)),
+            "too_many_poll_choices"
+        );
+
+        let poll = vote(1, &[1], &mut conn).unwrap();
+        assert!(poll.voted_by_user);
+        assert_eq!(poll.options[1].votes, 1);
+
+        assert_eq!(error_code(vote(1, &[0], &mut conn)), "already_voted");
+        let poll = load_polls(&[1], 2, &mut conn).unwrap().remove(&1).unwrap();
+        assert_eq!(poll.options[0].votes, 0);
+        assert_eq!(poll.options[1].votes, 1);
+    }
+
+    #[test]
+    fn multiple_choice_polls_take_several_choices_once() {
+        let mut conn = test_connection();
+        seed_polls(&mut conn);
+
+        assert_eq!(error_code(vote(2, &[], &mut conn)), "poll_choice_missing");
+        assert_eq!(error_code(vote(2, &[5], &mut conn)), "invalid_poll_choice");
+
+        let poll = vote(2, &[0, 2, 2], &mut conn).unwrap();
+        let votes: Vec<i32> = poll.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, vec![1, 0, 1]);
+
+        assert_eq!(error_code(vote(2, &[1], &mut conn)), "already_voted");
+    }
+}
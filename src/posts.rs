@@ -3,25 +3,45 @@ use actix_web::{
     web::{self, ServiceConfig},
     Error, HttpResponse,
 };
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
-    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, QueryDsl, Queryable,
-    RunQueryDsl, Selectable, SelectableHelper,
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, OptionalExtension, QueryDsl,
+    Queryable, RunQueryDsl, Selectable, SelectableHelper,
 };
 use microblogs::{
-    errors::ServiceError,
+    errors::{FieldError, ServiceError},
     generate_uid,
+    hub::{Event, EventHub, NotificationKind},
+    message,
     schema::{self, posts::like_count},
-    DbPool,
+    text::{grapheme_count, normalize},
+    AppState, DbConn, WritePool, LIKE_REACTION, MAX_POST_BODY_CHARS,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::users::UserDetails;
+use crate::{
+    attachments::attach_to_post,
+    bookmarks::BookmarkCollection,
+    link_previews::fetch_in_background,
+    polls::{create_poll, PollCreate},
+    users::UserDetails,
+    visibility::{visible_to, Visibility},
+};
+
+const MAX_CONTENT_WARNING_LENGTH: usize = 256;
 
 #[derive(Deserialize)]
 struct PostCreate {
     parent_uuid: Option<String>,
+    quote_uuid: Option<String>,
     body: String,
+    poll: Option<PollCreate>,
+    visibility: Option<Visibility>,
+    content_warning: Option<String>,
+    #[serde(default)]
+    sensitive_media: bool,
+    #[serde(default)]
+    attachments: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -29,45 +49,103 @@ struct PostLikeQuery {
     uuid: String,
 }
 
+#[derive(Deserialize)]
+struct PostReactionQuery {
+    uuid: String,
+    emoji: String,
+}
+
+#[derive(Deserialize)]
+struct PostRepostQuery {
+    uuid: String,
+}
+
+#[derive(Deserialize)]
+struct PostBookmarkQuery {
+    collection: Option<String>,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = schema::posts)]
-struct NewPost<'a> {
+pub struct NewPost<'a> {
     pub uuid: String,
     pub parent_id: Option<i32>,
     pub poster_id: i32,
     pub body: &'a str,
+    pub repost_of_id: Option<i32>,
+    pub quote_of_id: Option<i32>,
+    pub published: bool,
+    pub publish_at: Option<NaiveDateTime>,
+    pub visibility: &'a str,
+    pub content_warning: Option<&'a str>,
+    pub sensitive_media: bool,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = schema::likes)]
-struct NewLike {
+struct NewLike<'a> {
     pub user_id: i32,
     pub post_id: i32,
+    pub emoji: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::mentions)]
+struct NewMention {
+    pub post_id: i32,
+    pub user_id: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::bookmarks)]
+struct NewBookmark {
+    pub user_id: i32,
+    pub post_id: i32,
+    pub collection_id: Option<i32>,
 }
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = schema::posts)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(microblogs::DbBackend))]
 pub struct Post {
     pub id: i32,
     pub uuid: String,
+    pub poster_id: i32,
     pub body: String,
     pub created_at: NaiveDateTime,
     pub reply_count: i32,
     pub like_count: i32,
+    pub repost_of_id: Option<i32>,
+    pub quote_of_id: Option<i32>,
+    pub repost_count: i32,
+    pub quote_count: i32,
+    pub visibility: String,
+    pub content_warning: Option<String>,
+    pub sensitive_media: bool,
+    pub pinned_at: Option<NaiveDateTime>,
 }
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name=schema::likes)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(microblogs::DbBackend))]
 pub struct Like {
     pub id: i32,
     pub user_id: i32,
     pub post_id: i32,
+    pub emoji: String,
     pub created_at: NaiveDateTime,
     pub deleted: bool,
 }
 
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::bookmarks)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+pub struct Bookmark {
+    pub id: i32,
+    pub collection_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
 #[derive(Serialize)]
 struct PostRead {
     uuid: String,
@@ -79,10 +157,31 @@ impl From<Post> for PostRead {
     }
 }
 
+#[derive(Serialize)]
+struct BookmarkRead {
+    post_uuid: String,
+    collection: Option<String>,
+    created_at: String,
+}
+
+fn counts_event(post: &Post) -> Event {
+    Event::PostCounts {
+        poster_id: post.poster_id,
+        open: post.visibility == Visibility::Public.as_str()
+            || post.visibility == Visibility::Unlisted.as_str(),
+        uuid: post.uuid.clone(),
+        reply_count: post.reply_count,
+        like_count: post.like_count,
+        repost_count: post.repost_count,
+        quote_count: post.quote_count,
+    }
+}
+
 #[derive(Serialize)]
 struct LikeRead {
     user_id: i32,
     post_id: i32,
+    emoji: String,
     created_at: String,
     deleted: bool,
 }
@@ -92,157 +191,426 @@ impl From<Like> for LikeRead {
         LikeRead {
             user_id: like.user_id,
             post_id: like.post_id,
+            emoji: like.emoji,
             created_at: like.created_at.to_string(),
             deleted: like.deleted,
         }
     }
 }
 
+pub struct ValidatedContent {
+    pub body: String,
+    pub content_warning: Option<String>,
+}
+
+// Normalizes the body and content warning of a post, reporting every invalid
+// field at once. A blank content warning counts as no warning at all.
+pub fn validate_content(
+    body: &str,
+    content_warning: &Option<String>,
+    allow_empty: bool,
+    max_post_length: usize,
+) -> Result<ValidatedContent, ServiceError> {
+    let mut errors = Vec::new();
+
+    let body = normalize(body);
+    if body.is_empty() && !allow_empty {
+        errors.push(FieldError::new("body", "empty", message!("empty-post")));
+    }
+    let body_length = grapheme_count(&body);
+    let body_chars = body.chars().count();
+    if body_length > max_post_length {
+        errors.push(FieldError::new(
+            "body",
+            "too_long",
+            message!("post-too-long", max = max_post_length, length = body_length),
+        ));
+    } else if body_chars > MAX_POST_BODY_CHARS {
+        errors.push(FieldError::new(
+            "body",
+            "too_long",
+            message!(
+                "post-too-long",
+                max = MAX_POST_BODY_CHARS,
+                length = body_chars
+            ),
+        ));
+    }
+
+    let content_warning = content_warning
+        .as_deref()
+        .map(normalize)
+        .filter(|content_warning| !content_warning.is_empty());
+    if let Some(content_warning) = &content_warning {
+        if grapheme_count(content_warning) > MAX_CONTENT_WARNING_LENGTH {
+            errors.push(FieldError::new(
+                "content_warning",
+                "too_long",
+                message!("content-warning-too-long", max = MAX_CONTENT_WARNING_LENGTH),
+            ));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(ServiceError::InvalidFields(errors));
+    }
+
+    Ok(ValidatedContent {
+        body,
+        content_warning,
+    })
+}
+
+fn mentioned_usernames(body: &str) -> Vec<String> {
+    body.split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|name| name.trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_')))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// Replaces the mentions of a post with the users mentioned in its body, which
+// decide who can see direct posts. Users who blocked the poster are left out.
+// A direct reply also mentions the author of the post it answers, so they can
+// see the reply.
+pub fn record_mentions(
+    target_post_id: i32,
+    target_poster_id: i32,
+    post_body: &str,
+    conn: &mut DbConn,
+) -> Result<(), diesel::result::Error> {
+    use schema::blocks::dsl::{blocked_id, blocker_id, blocks, deleted as block_deleted};
+    use schema::mentions::dsl::{mentions, post_id};
+    use schema::posts::dsl::{
+        id as posts_id, parent_id, poster_id as posts_poster_id, posts, visibility,
+    };
+    use schema::users::dsl::{deleted, id, username, users};
+
+    diesel::delete(mentions.filter(post_id.eq(target_post_id))).execute(conn)?;
+
+    let (target_parent_id, target_visibility): (Option<i32>, String) = posts
+        .filter(posts_id.eq(target_post_id))
+        .select((parent_id, visibility))
+        .first(conn)?;
+    let parent_poster_id = match target_parent_id {
+        Some(target_parent_id) if target_visibility == Visibility::Direct.as_str() => posts
+            .filter(posts_id.eq(target_parent_id))
+            .select(posts_poster_id)
+            .first::<i32>(conn)
+            .optional()?
+            .filter(|parent_poster_id| *parent_poster_id != target_poster_id),
+        _ => None,
+    };
+
+    let usernames = mentioned_usernames(post_body);
+    let mut mentioned_ids: Vec<i32> = if usernames.is_empty() {
+        Vec::new()
+    } else {
+        users
+            .filter(
+                username.eq_any(usernames).and(deleted.eq(false)).and(
+                    id.ne_all(
+                        blocks
+                            .filter(blocked_id.eq(target_poster_id).and(block_deleted.eq(false)))
+                            .select(blocker_id),
+                    ),
+                ),
+            )
+            .select(id)
+            .load(conn)?
+    };
+    if let Some(parent_poster_id) = parent_poster_id {
+        if !mentioned_ids.contains(&parent_poster_id) {
+            mentioned_ids.push(parent_poster_id);
+        }
+    }
+    if mentioned_ids.is_empty() {
+        return Ok(());
+    }
+
+    let new_mentions: Vec<NewMention> = mentioned_ids
+        .into_iter()
+        .map(|mentioned_id| NewMention {
+            post_id: target_post_id,
+            user_id: mentioned_id,
+        })
+        .collect();
+
+    diesel::insert_into(mentions)
+        .values(&new_mentions)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+pub fn publish_created(
+    hub: &EventHub,
+    post: &Post,
+    parent: Option<Post>,
+    quoted: Option<Post>,
+    poster_username: String,
+) {
+    if let Some(quoted) = quoted {
+        hub.publish(counts_event(&quoted));
+        if quoted.poster_id != post.poster_id {
+            hub.publish(Event::Notification {
+                recipient_id: quoted.poster_id,
+                kind: NotificationKind::Quote,
+                post_uuid: post.uuid.clone(),
+                actor_username: poster_username.clone(),
+            });
+        }
+    }
+
+    match parent {
+        Some(parent) => {
+            hub.publish(counts_event(&parent));
+            if parent.poster_id != post.poster_id {
+                hub.publish(Event::Notification {
+                    recipient_id: parent.poster_id,
+                    kind: NotificationKind::Reply,
+                    post_uuid: post.uuid.clone(),
+                    actor_username: poster_username,
+                });
+            }
+        }
+        // each subscriber's stream decides whether the post is in their audience
+        None => hub.publish(Event::NewPost {
+            poster_id: post.poster_id,
+            visibility: post.visibility.clone(),
+            uuid: post.uuid.clone(),
+            poster_username,
+        }),
+    }
+}
+
 #[post("/create")]
 async fn create_post(
     info: web::Json<PostCreate>,
-    pool: web::Data<DbPool>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    app_state: web::Data<AppState>,
     current_user: UserDetails,
 ) -> Result<HttpResponse, Error> {
     use schema::posts::dsl::*;
 
-    let post = web::block(move || {
+    if let Some(poll) = &info.poll {
+        poll.validate()?;
+    }
+    let content = validate_content(
+        &info.body,
+        &info.content_warning,
+        !info.attachments.is_empty() || info.poll.is_some(),
+        app_state.max_post_length,
+    )?;
+
+    let poster_username = current_user.username.clone();
+    let preview_pool = pool.get_ref().clone();
+    let (post, parent, quoted) = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
-            Err(_) => return Err(ServiceError::InternalServerError(format!("Impossível conectar ao banco de dados."))),
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
         };
 
-        let result = conn.transaction::<Post, diesel::result::Error, _>(|conn| {
-            let updated_parent_id = match &info.parent_uuid {
+        conn.transaction::<(Post, Option<Post>, Option<Post>), ServiceError, _>(|conn| {
+            let parent = match &info.parent_uuid {
                 Some(parent_uuid) => Some(
                     diesel::update(posts)
-                        .filter(uuid.eq(parent_uuid))
+                        .filter(
+                            uuid.eq(parent_uuid)
+                                .and(deleted.eq(false))
+                                .and(published.eq(true))
+                                .and(visible_to(current_user.id)),
+                        )
                         .set(reply_count.eq(reply_count + 1))
                         .returning(Post::as_returning())
-                        .get_result(conn)?
-                        .id,
+                        .get_result(conn)
+                        .optional()?
+                        .ok_or_else(|| {
+                            ServiceError::BadRequest(
+                                "parent_post_not_found",
+                                message!("parent-post-not-found", uuid = parent_uuid),
+                            )
+                        })?,
+                ),
+                None => None,
+            };
+
+            let quoted = match &info.quote_uuid {
+                Some(quoted_uuid) => Some(
+                    diesel::update(posts)
+                        .filter(
+                            uuid.eq(quoted_uuid)
+                                .and(deleted.eq(false))
+                                .and(published.eq(true))
+                                .and(visible_to(current_user.id)),
+                        )
+                        .set(quote_count.eq(quote_count + 1))
+                        .returning(Post::as_returning())
+                        .get_result(conn)
+                        .optional()?
+                        .ok_or_else(|| {
+                            ServiceError::BadRequest(
+                                "quoted_post_not_found",
+                                message!("quoted-post-not-found", uuid = quoted_uuid),
+                            )
+                        })?,
                 ),
                 None => None,
             };
 
+            // replies keep the audience of the conversation unless told otherwise
+            let post_visibility = match (info.visibility, &parent) {
+                (Some(chosen), _) => chosen.as_str().to_string(),
+                (None, Some(parent)) => parent.visibility.clone(),
+                (None, None) => Visibility::Public.as_str().to_string(),
+            };
+
             let post_uuid = generate_uid();
             let new_post = NewPost {
                 uuid: post_uuid,
-                parent_id: updated_parent_id,
+                parent_id: parent.as_ref().map(|parent| parent.id),
                 poster_id: current_user.id,
-                body: &info.body,
+                body: &content.body,
+                repost_of_id: None,
+                quote_of_id: quoted.as_ref().map(|quoted| quoted.id),
+                published: true,
+                publish_at: None,
+                visibility: &post_visibility,
+                content_warning: content.content_warning.as_deref(),
+                sensitive_media: info.sensitive_media,
             };
 
-            let post = match diesel::insert_into(posts)
+            let post = diesel::insert_into(posts)
                 .values(&new_post)
                 .returning(Post::as_returning())
-                .get_result(conn)
-            {
-                Ok(post) => post,
-                Err(_) => return Err(diesel::result::Error::RollbackTransaction),
-            };
-
-            Ok(post)
-        });
+                .get_result(conn)?;
 
-        match result {
-            Ok(post) => Ok(post),
-            Err(_) => {
-                if let Some(parent_uuid) = &info.parent_uuid {
-                    Err(ServiceError::BadRequest(format!("Não foi possível criar a postagem. Talvez a postagem {} (a qual você está tentando responder) não exista ou o corpo de texto fornecido seja inválido.", parent_uuid)))
-                } else {
-                    Err(ServiceError::BadRequest(format!("Não foi possível criar a postagem. Talvez o corpo de texto fornecido seja inválido.")))
+            if let Some(poll) = &info.poll {
+                create_poll(post.id, poll, conn)?;
+            }
+            // `attach_to_post` rolls back when an attachment can't be used
+            attach_to_post(post.id, current_user.id, &info.attachments, conn).map_err(|err| {
+                match err {
+                    diesel::result::Error::RollbackTransaction => ServiceError::BadRequest(
+                        "attachments_unavailable",
+                        message!("attachments-unavailable"),
+                    ),
+                    err => ServiceError::from(err),
                 }
-            },
-        }
+            })?;
+            record_mentions(post.id, post.poster_id, &post.body, conn)?;
+
+            Ok((post, parent, quoted))
+        })
     })
     .await??;
 
+    publish_created(&hub, &post, parent, quoted, poster_username);
+    fetch_in_background(&post.body, preview_pool, app_state.link_previews.clone());
+
     Ok(HttpResponse::Ok().json(PostRead::from(post)))
 }
 
-#[post("/like")]
-async fn like_post(
-    post_like: web::Query<PostLikeQuery>,
-    pool: web::Data<DbPool>,
+async fn add_reaction(
+    target_post_uuid: String,
+    reaction: String,
+    pool: web::Data<WritePool>,
     current_user: UserDetails,
-) -> Result<HttpResponse, Error> {
-    use schema::likes::dsl::{deleted as like_deleted, likes, user_id as like_user_id};
-    use schema::posts::dsl::{deleted as post_deleted, id as post_id, posts, uuid as post_uuid};
+) -> Result<(Like, Post), Error> {
+    use schema::likes::dsl::likes;
+    use schema::posts::dsl::{
+        deleted as post_deleted, id as post_id, posts, published as post_published,
+        uuid as post_uuid,
+    };
 
     let result = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
-            Err(_) => return Err(ServiceError::InternalServerError(format!("Impossível conectar ao banco de dados."))),
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
         };
 
-        let like = conn.transaction::<Like, diesel::result::Error, _>(|conn| {
-            if let Ok((_, post)) = likes
-                .inner_join(posts)
+        let like = conn.transaction::<(Like, Post), diesel::result::Error, _>(|conn| {
+            let post: Post = posts
                 .filter(
                     post_uuid
-                        .eq(&post_like.uuid)
-                        .and(like_user_id.eq(current_user.id))
-                        .and(like_deleted.eq(false)),
+                        .eq(&target_post_uuid)
+                        .and(post_deleted.eq(false))
+                        .and(post_published.eq(true))
+                        .and(visible_to(current_user.id)),
                 )
-                .select((Like::as_select(), Post::as_select()))
-                .first(conn)
-            {
-                return Err(diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::UniqueViolation,
-                    Box::new(format!(
-                        "User {} already liked post {}",
-                        current_user.username, post.uuid
-                    )),
-                ));
-            };
-
-            let post: Post = posts
-                .filter(post_uuid.eq(&post_like.uuid))
                 .select(Post::as_select())
                 .first(conn)?;
 
             let new_like = NewLike {
                 post_id: post.id,
                 user_id: current_user.id,
+                emoji: &reaction,
             };
 
-            let like = match diesel::insert_into(likes)
+            let like = diesel::insert_into(likes)
                 .values(&new_like)
                 .returning(Like::as_returning())
-                .get_result(conn)
-            {
-                Ok(like) => {
-                    diesel::update(posts)
-                        .filter(post_id.eq(post.id).and(post_deleted.eq(false)))
-                        .set(like_count.eq(like_count + 1))
-                        .execute(conn)?;
-                    like
-                }
-                Err(_) => return Err(diesel::result::Error::RollbackTransaction),
-            };
+                .get_result(conn)?;
+
+            // only the like reaction is counted in `like_count`
+            if reaction != LIKE_REACTION {
+                return Ok((like, post));
+            }
+
+            let post = diesel::update(posts)
+                .filter(post_id.eq(post.id).and(post_deleted.eq(false)))
+                .set(like_count.eq(like_count + 1))
+                .returning(Post::as_returning())
+                .get_result(conn)?;
 
-            Ok(like)
+            Ok((like, post))
         });
 
         match like {
-            Ok(like) => Ok(like),
-            Err(_) => Err(ServiceError::BadRequest(format!("Impossível curtir a postagem {}. Talvez a postagem {} (a qual você está tentando curtir) não exista ou você já tenha curtido.", post_like.uuid, post_like.uuid))),
+            Ok(result) => Ok(result),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "post_not_found",
+                message!("post-not-found", uuid = target_post_uuid),
+            )),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(ServiceError::Conflict(
+                "already_reacted",
+                message!(
+                    "already-reacted",
+                    uuid = target_post_uuid,
+                    reaction = reaction
+                ),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
         }
     })
     .await??;
 
-    Ok(HttpResponse::Ok().json(LikeRead::from(result)))
+    Ok(result)
 }
 
-#[delete("/like")]
-async fn unlike_post(
-    post_like: web::Query<PostLikeQuery>,
-    pool: web::Data<DbPool>,
+async fn remove_reaction(
+    target_post_uuid: String,
+    reaction: String,
+    pool: web::Data<WritePool>,
     current_user: UserDetails,
-) -> Result<HttpResponse, Error> {
+) -> Result<(Like, Post), Error> {
     use schema::likes::dsl::{
-        deleted as like_deleted, id as like_id, likes, user_id as like_user_id,
+        deleted as like_deleted, emoji, id as like_id, likes, user_id as like_user_id,
     };
     use schema::posts::dsl::{deleted as post_deleted, id as post_id, posts, uuid as post_uuid};
 
@@ -250,20 +618,22 @@ async fn unlike_post(
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
-        let like = conn.transaction::<Like, diesel::result::Error, _>(|conn| {
-            // check if like exists
+        let like = conn.transaction::<(Like, Post), diesel::result::Error, _>(|conn| {
+            // check if reaction exists
             let (like, post) = likes
                 .inner_join(posts)
                 .filter(
                     post_uuid
-                        .eq(&post_like.uuid)
+                        .eq(&target_post_uuid)
                         .and(like_user_id.eq(current_user.id))
+                        .and(emoji.eq(&reaction))
                         .and(like_deleted.eq(false)),
                 )
                 .select((Like::as_select(), Post::as_select()))
@@ -275,26 +645,613 @@ async fn unlike_post(
                 .set(like_deleted.eq(true))
                 .execute(conn)?;
 
+            if reaction != LIKE_REACTION {
+                return Ok((like, post));
+            }
+
             // update post like count
-            diesel::update(posts)
+            let post = diesel::update(posts)
                 .filter(post_id.eq(post.id).and(post_deleted.eq(false)))
                 .set(like_count.eq(like_count - 1))
-                .execute(conn)?;
+                .returning(Post::as_returning())
+                .get_result(conn)?;
 
-            Ok(like)
+            Ok((like, post))
         });
 
         match like {
-            Ok(like) => Ok(like),
-            Err(_) => Err(ServiceError::BadRequest(format!(
-                "Não é possível remover a curtida do post {}. Talvez a curtida nem exista.",
-                post_like.uuid
-            ))),
+            Ok(result) => Ok(result),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "reaction_not_found",
+                message!(
+                    "reaction-not-found",
+                    reaction = reaction,
+                    uuid = target_post_uuid
+                ),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
         }
     })
     .await??;
 
-    Ok(HttpResponse::Ok().json(LikeRead::from(result)))
+    Ok(result)
+}
+
+fn publish_reaction(hub: &EventHub, like: &Like, post: &Post, actor_username: String) {
+    hub.publish(counts_event(post));
+    if post.poster_id != like.user_id {
+        hub.publish(Event::Notification {
+            recipient_id: post.poster_id,
+            kind: if like.emoji == LIKE_REACTION {
+                NotificationKind::Like
+            } else {
+                NotificationKind::Reaction
+            },
+            post_uuid: post.uuid.clone(),
+            actor_username,
+        });
+    }
+}
+
+#[post("/like")]
+async fn like_post(
+    post_like: web::Query<PostLikeQuery>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    let actor_username = current_user.username.clone();
+    let (like, post) = add_reaction(
+        post_like.into_inner().uuid,
+        LIKE_REACTION.to_string(),
+        pool,
+        current_user,
+    )
+    .await?;
+
+    publish_reaction(&hub, &like, &post, actor_username);
+
+    Ok(HttpResponse::Ok().json(LikeRead::from(like)))
+}
+
+#[delete("/like")]
+async fn unlike_post(
+    post_like: web::Query<PostLikeQuery>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    let (like, post) = remove_reaction(
+        post_like.into_inner().uuid,
+        LIKE_REACTION.to_string(),
+        pool,
+        current_user,
+    )
+    .await?;
+
+    hub.publish(counts_event(&post));
+
+    Ok(HttpResponse::Ok().json(LikeRead::from(like)))
+}
+
+#[post("/react")]
+async fn react_to_post(
+    post_reaction: web::Query<PostReactionQuery>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    app_state: web::Data<AppState>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    let PostReactionQuery { uuid, emoji } = post_reaction.into_inner();
+    if !app_state.reaction_emojis.contains(&emoji) {
+        return Err(ServiceError::BadRequest(
+            "reaction_unavailable",
+            message!("reaction-unavailable", reaction = emoji),
+        )
+        .into());
+    }
+
+    let actor_username = current_user.username.clone();
+    let (like, post) = add_reaction(uuid, emoji, pool, current_user).await?;
+
+    publish_reaction(&hub, &like, &post, actor_username);
+
+    Ok(HttpResponse::Ok().json(LikeRead::from(like)))
+}
+
+#[delete("/react")]
+async fn unreact_to_post(
+    post_reaction: web::Query<PostReactionQuery>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    let PostReactionQuery { uuid, emoji } = post_reaction.into_inner();
+    let (like, post) = remove_reaction(uuid, emoji, pool, current_user).await?;
+
+    hub.publish(counts_event(&post));
+
+    Ok(HttpResponse::Ok().json(LikeRead::from(like)))
+}
+
+#[post("/repost")]
+async fn repost_post(
+    post_repost: web::Query<PostRepostQuery>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{
+        deleted, id as post_id, posts, published, repost_count, uuid as post_uuid,
+        visibility as post_visibility,
+    };
+
+    let actor_username = current_user.username.clone();
+    let (repost, original) = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<(Post, Post), diesel::result::Error, _>(|conn| {
+            let target: Post = posts
+                .filter(
+                    post_uuid
+                        .eq(&post_repost.uuid)
+                        .and(deleted.eq(false))
+                        .and(published.eq(true))
+                        .and(post_visibility.eq_any(vec![
+                            Visibility::Public.as_str(),
+                            Visibility::Unlisted.as_str(),
+                        ]))
+                        .and(visible_to(current_user.id)),
+                )
+                .select(Post::as_select())
+                .first(conn)?;
+
+            // reposting a repost shares the original post instead
+            let original_id = target.repost_of_id.unwrap_or(target.id);
+
+            let original = diesel::update(posts)
+                .filter(post_id.eq(original_id).and(deleted.eq(false)))
+                .set(repost_count.eq(repost_count + 1))
+                .returning(Post::as_returning())
+                .get_result(conn)?;
+
+            let new_post = NewPost {
+                uuid: generate_uid(),
+                parent_id: None,
+                poster_id: current_user.id,
+                body: "",
+                repost_of_id: Some(original.id),
+                quote_of_id: None,
+                published: true,
+                publish_at: None,
+                // a repost reaches no further than the post it shares
+                visibility: &original.visibility,
+                content_warning: None,
+                sensitive_media: false,
+            };
+
+            let repost = diesel::insert_into(posts)
+                .values(&new_post)
+                .returning(Post::as_returning())
+                .get_result(conn)?;
+
+            Ok((repost, original))
+        });
+
+        match result {
+            Ok(result) => Ok(result),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "post_not_found",
+                message!("post-not-found", uuid = post_repost.uuid),
+            )),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(ServiceError::Conflict(
+                "already_reposted",
+                message!("already-reposted", uuid = post_repost.uuid),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
+        }
+    })
+    .await??;
+
+    hub.publish(counts_event(&original));
+    hub.publish(Event::NewPost {
+        poster_id: repost.poster_id,
+        visibility: repost.visibility.clone(),
+        uuid: repost.uuid.clone(),
+        poster_username: actor_username.clone(),
+    });
+    if original.poster_id != repost.poster_id {
+        hub.publish(Event::Notification {
+            recipient_id: original.poster_id,
+            kind: NotificationKind::Repost,
+            post_uuid: original.uuid,
+            actor_username,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(PostRead::from(repost)))
+}
+
+#[delete("/repost")]
+async fn unrepost_post(
+    post_repost: web::Query<PostRepostQuery>,
+    pool: web::Data<WritePool>,
+    hub: web::Data<EventHub>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{
+        deleted, id as post_id, poster_id, posts, repost_count, repost_of_id, uuid as post_uuid,
+    };
+
+    let original = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Post, diesel::result::Error, _>(|conn| {
+            let target: Post = posts
+                .filter(post_uuid.eq(&post_repost.uuid).and(deleted.eq(false)))
+                .select(Post::as_select())
+                .first(conn)?;
+            let original_id = target.repost_of_id.unwrap_or(target.id);
+
+            // set the repost as deleted
+            let repost: Post = diesel::update(posts)
+                .filter(
+                    poster_id
+                        .eq(current_user.id)
+                        .and(repost_of_id.eq(original_id))
+                        .and(deleted.eq(false)),
+                )
+                .set(deleted.eq(true))
+                .returning(Post::as_returning())
+                .get_result(conn)?;
+
+            // update original repost count
+            let original = diesel::update(posts)
+                .filter(post_id.eq(repost.repost_of_id.unwrap_or(original_id)))
+                .set(repost_count.eq(repost_count - 1))
+                .returning(Post::as_returning())
+                .get_result(conn)?;
+
+            Ok(original)
+        });
+
+        match result {
+            Ok(original) => Ok(original),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "repost_not_found",
+                message!("repost-not-found", uuid = post_repost.uuid),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
+        }
+    })
+    .await??;
+
+    hub.publish(counts_event(&original));
+
+    Ok(HttpResponse::Ok().json(PostRead::from(original)))
+}
+
+#[post("/{target_post_uuid}/bookmark")]
+async fn bookmark_post(
+    target_post_uuid: web::Path<String>,
+    bookmark_query: web::Query<PostBookmarkQuery>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::bookmark_collections::dsl::{
+        bookmark_collections, deleted as collection_deleted, owner_id, uuid as collection_uuid,
+    };
+    use schema::bookmarks::dsl::{
+        bookmarks, collection_id, deleted as bookmark_deleted, id as bookmark_id,
+        post_id as bookmark_post_id, user_id as bookmark_user_id,
+    };
+    use schema::posts::dsl::{
+        deleted as post_deleted, posts, published as post_published, uuid as post_uuid,
+    };
+
+    let collection = bookmark_query.collection.clone();
+    let (bookmark, post) = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let target_collection_id = match &bookmark_query.collection {
+            Some(target_collection_uuid) => match bookmark_collections
+                .filter(
+                    collection_uuid
+                        .eq(target_collection_uuid)
+                        .and(owner_id.eq(current_user.id))
+                        .and(collection_deleted.eq(false)),
+                )
+                .select(BookmarkCollection::as_select())
+                .first(&mut conn)
+            {
+                Ok(collection) => Some(collection.id),
+                Err(diesel::result::Error::NotFound) => {
+                    return Err(ServiceError::NotFound(
+                        "collection_not_found",
+                        message!("collection-not-found", uuid = target_collection_uuid),
+                    ))
+                }
+                Err(err) => return Err(ServiceError::from(err)),
+            },
+            None => None,
+        };
+
+        let result = conn.transaction::<(Bookmark, Post), diesel::result::Error, _>(|conn| {
+            let post: Post = posts
+                .filter(
+                    post_uuid
+                        .eq(target_post_uuid.as_str())
+                        .and(post_deleted.eq(false))
+                        .and(post_published.eq(true))
+                        .and(visible_to(current_user.id)),
+                )
+                .select(Post::as_select())
+                .first(conn)?;
+
+            // bookmarking again just moves the bookmark to another collection
+            if let Some(bookmark) = bookmarks
+                .filter(
+                    bookmark_post_id
+                        .eq(post.id)
+                        .and(bookmark_user_id.eq(current_user.id))
+                        .and(bookmark_deleted.eq(false)),
+                )
+                .select(Bookmark::as_select())
+                .first(conn)
+                .optional()?
+            {
+                let bookmark = diesel::update(bookmarks)
+                    .filter(bookmark_id.eq(bookmark.id))
+                    .set(collection_id.eq(target_collection_id))
+                    .returning(Bookmark::as_returning())
+                    .get_result(conn)?;
+                return Ok((bookmark, post));
+            }
+
+            let new_bookmark = NewBookmark {
+                user_id: current_user.id,
+                post_id: post.id,
+                collection_id: target_collection_id,
+            };
+
+            let bookmark = diesel::insert_into(bookmarks)
+                .values(&new_bookmark)
+                .returning(Bookmark::as_returning())
+                .get_result(conn)?;
+
+            Ok((bookmark, post))
+        });
+
+        match result {
+            Ok(result) => Ok(result),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "post_not_found",
+                message!("post-not-found", uuid = target_post_uuid),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(BookmarkRead {
+        post_uuid: post.uuid,
+        collection: bookmark.collection_id.and(collection),
+        created_at: bookmark.created_at.to_string(),
+    }))
+}
+
+#[post("/{target_post_uuid}/pin")]
+async fn pin_post(
+    target_post_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    app_state: web::Data<AppState>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{
+        deleted, id as post_id, pinned_at, poster_id, posts, published, repost_of_id,
+        uuid as post_uuid,
+    };
+
+    let max_pinned_posts = app_state.max_pinned_posts;
+    let post = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        conn.transaction::<Post, ServiceError, _>(|conn| {
+            // only own published posts can be pinned, and reposts cannot
+            let post: Post = match posts
+                .filter(
+                    post_uuid
+                        .eq(target_post_uuid.as_str())
+                        .and(poster_id.eq(current_user.id))
+                        .and(published.eq(true))
+                        .and(deleted.eq(false))
+                        .and(repost_of_id.is_null()),
+                )
+                .select(Post::as_select())
+                .first(conn)
+                .optional()?
+            {
+                Some(post) => post,
+                None => {
+                    return Err(ServiceError::NotFound(
+                        "post_not_found",
+                        message!("post-not-found", uuid = target_post_uuid),
+                    ))
+                }
+            };
+            if post.pinned_at.is_some() {
+                return Err(ServiceError::Conflict(
+                    "already_pinned",
+                    message!("already-pinned", uuid = target_post_uuid),
+                ));
+            }
+
+            let pinned_count: i64 = posts
+                .filter(
+                    poster_id
+                        .eq(current_user.id)
+                        .and(pinned_at.is_not_null())
+                        .and(deleted.eq(false)),
+                )
+                .count()
+                .get_result(conn)?;
+            if pinned_count >= max_pinned_posts {
+                return Err(ServiceError::Conflict(
+                    "pin_limit_reached",
+                    message!("pin-limit-reached", max = max_pinned_posts),
+                ));
+            }
+
+            Ok(diesel::update(posts)
+                .filter(post_id.eq(post.id))
+                .set(pinned_at.eq(Utc::now().naive_utc()))
+                .returning(Post::as_returning())
+                .get_result(conn)?)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(PostRead::from(post)))
+}
+
+#[delete("/{target_post_uuid}/pin")]
+async fn unpin_post(
+    target_post_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::posts::dsl::{pinned_at, poster_id, posts, uuid as post_uuid};
+
+    let post = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match diesel::update(posts)
+            .filter(
+                post_uuid
+                    .eq(target_post_uuid.as_str())
+                    .and(poster_id.eq(current_user.id))
+                    .and(pinned_at.is_not_null()),
+            )
+            .set(pinned_at.eq(None::<NaiveDateTime>))
+            .returning(Post::as_returning())
+            .get_result(&mut conn)
+        {
+            Ok(post) => Ok(post),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "pin_not_found",
+                message!("pin-not-found", uuid = target_post_uuid),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(PostRead::from(post)))
+}
+
+#[delete("/{target_post_uuid}/bookmark")]
+async fn unbookmark_post(
+    target_post_uuid: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::bookmarks::dsl::{
+        bookmarks, deleted as bookmark_deleted, id as bookmark_id, user_id as bookmark_user_id,
+    };
+    use schema::posts::dsl::{posts, uuid as post_uuid};
+
+    let (bookmark, post) = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<(Bookmark, Post), diesel::result::Error, _>(|conn| {
+            // check if bookmark exists
+            let (bookmark, post) = bookmarks
+                .inner_join(posts)
+                .filter(
+                    post_uuid
+                        .eq(target_post_uuid.as_str())
+                        .and(bookmark_user_id.eq(current_user.id))
+                        .and(bookmark_deleted.eq(false)),
+                )
+                .select((Bookmark::as_select(), Post::as_select()))
+                .first(conn)?;
+
+            // set as deleted
+            diesel::update(bookmarks)
+                .filter(bookmark_id.eq(bookmark.id))
+                .set(bookmark_deleted.eq(true))
+                .execute(conn)?;
+
+            Ok((bookmark, post))
+        });
+
+        match result {
+            Ok(result) => Ok(result),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "bookmark_not_found",
+                message!("bookmark-not-found", uuid = target_post_uuid),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(BookmarkRead {
+        post_uuid: post.uuid,
+        collection: None,
+        created_at: bookmark.created_at.to_string(),
+    }))
 }
 
 pub fn configure(cfg: &mut ServiceConfig) {
@@ -302,6 +1259,100 @@ pub fn configure(cfg: &mut ServiceConfig) {
         web::scope("/posts")
             .service(create_post)
             .service(like_post)
-            .service(unlike_post),
+            .service(unlike_post)
+            .service(react_to_post)
+            .service(unreact_to_post)
+            .service(repost_post)
+            .service(unrepost_post)
+            .service(bookmark_post)
+            .service(unbookmark_post)
+            .service(pin_post)
+            .service(unpin_post),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_errors(
+        body: &str,
+        content_warning: Option<&str>,
+        allow_empty: bool,
+        max_post_length: usize,
+    ) -> Vec<(String, &'static str)> {
+        let content_warning = content_warning.map(str::to_string);
+        match validate_content(body, &content_warning, allow_empty, max_post_length) {
+            Ok(_) => Vec::new(),
+            Err(ServiceError::InvalidFields(errors)) => errors
+                .into_iter()
+                .map(|error| (error.field, error.code))
+                .collect(),
+            Err(err) => panic!("unexpected error {}", err.code()),
+        }
+    }
+
+    #[test]
+    fn counts_graphemes_against_the_limit() {
+        // a family emoji is several characters but a single grapheme
+        let family = "👨‍👩‍👧";
+        assert!(field_errors(&family.repeat(10), None, false, 10).is_empty());
+        assert_eq!(
+            field_errors(&family.repeat(11), None, false, 10),
+            vec![("body".to_string(), "too_long")]
+        );
+    }
+
+    #[test]
+    fn limits_characters_to_the_column_width() {
+        // within the grapheme limit, but wider than the `posts.body` column
+        let body = "👨‍👩‍👧".repeat(MAX_POST_BODY_CHARS / 5 + 1);
+        assert_eq!(
+            field_errors(&body, None, false, MAX_POST_BODY_CHARS),
+            vec![("body".to_string(), "too_long")]
+        );
+        assert!(field_errors(
+            &"a".repeat(MAX_POST_BODY_CHARS),
+            None,
+            false,
+            MAX_POST_BODY_CHARS
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn rejects_whitespace_only_bodies() {
+        assert_eq!(
+            field_errors(" \n\t ", None, false, 10),
+            vec![("body".to_string(), "empty")]
+        );
+        assert!(field_errors(" \n\t ", None, true, 10).is_empty());
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let content_warning = "x".repeat(MAX_CONTENT_WARNING_LENGTH + 1);
+        assert_eq!(
+            field_errors("", Some(&content_warning), false, 10),
+            vec![
+                ("body".to_string(), "empty"),
+                ("content_warning".to_string(), "too_long")
+            ]
+        );
+    }
+
+    #[test]
+    fn normalizes_body_and_content_warning() {
+        let content =
+            validate_content("  cafe\u{301}\u{7}\n ", &Some("   ".to_string()), false, 10)
+                .ok()
+                .unwrap();
+        assert_eq!(content.body, "caf\u{e9}");
+        assert_eq!(content.content_warning, None);
+
+        let content = validate_content("body", &Some(" spoilers ".to_string()), false, 10)
+            .ok()
+            .unwrap();
+        assert_eq!(content.content_warning.as_deref(), Some("spoilers"));
+    }
+}
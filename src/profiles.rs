@@ -1,31 +1,65 @@
 use actix_web::{
-    get,
+    delete, get, post,
     web::{self, ServiceConfig},
     Error, HttpResponse,
 };
 use diesel::{
-    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, Queryable, RunQueryDsl,
-    Selectable, SelectableHelper,
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, JoinOnDsl, QueryDsl,
+    Queryable, RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{
+    errors::ServiceError, message, schema, DbConn, DbPool, Pagination, WritePool, LIKE_REACTION,
 };
-use microblogs::{errors::ServiceError, schema, DbConn, DbPool, Pagination};
 use serde::Serialize;
 
 use crate::{
-    feeds::{PostRead, Poster},
-    posts::{Like, Post},
+    feeds::{apply_preferences, load_post_reads, load_posts_by_id, PostRead, PostRow, Poster},
+    posts::{Bookmark, Like, Post},
+    relationships::blocked_between,
     users::UserDetails,
+    visibility::visible_to,
 };
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = schema::users)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(microblogs::DbBackend))]
 struct Profile {
+    id: i32,
     username: String,
     real_name: String,
     summary: String,
     created_at: chrono::NaiveDateTime,
 }
 
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::users)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct LikesOwner {
+    id: i32,
+    likes_public: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::follows)]
+struct NewFollow {
+    pub follower_id: i32,
+    pub followee_id: i32,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::follows)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct Follow {
+    pub id: i32,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct FollowRead {
+    username: String,
+    created_at: String,
+}
+
 #[derive(Serialize)]
 struct ProfileRead {
     username: String,
@@ -39,6 +73,13 @@ struct ProfilePostsRead {
     posts: Vec<PostRead>,
 }
 
+#[derive(Serialize)]
+struct ProfileTimelineRead {
+    // only filled on the first page, so `posts` holds at most `limit` posts
+    pinned: Vec<PostRead>,
+    posts: Vec<PostRead>,
+}
+
 impl From<Profile> for ProfileRead {
     fn from(profile: Profile) -> Self {
         ProfileRead {
@@ -63,12 +104,13 @@ fn get_profile(target_username: &str, conn: &mut DbConn) -> Result<Profile, Serv
         .first(conn)
     {
         Ok(user) => user,
-        Err(_) => {
-            return Err(ServiceError::NotFound(format!(
-                "Usuário \"{}\" não encontrado.",
-                target_username
-            )))
+        Err(diesel::result::Error::NotFound) => {
+            return Err(ServiceError::NotFound(
+                "user_not_found",
+                message!("user-not-found", username = target_username),
+            ))
         }
+        Err(err) => return Err(err.into()),
     };
 
     Ok(profile)
@@ -78,20 +120,30 @@ fn get_profile(target_username: &str, conn: &mut DbConn) -> Result<Profile, Serv
 async fn get_profile_details(
     target_username: web::Path<String>,
     pool: web::Data<DbPool>,
-    _current_user: UserDetails,
+    current_user: UserDetails,
 ) -> Result<HttpResponse, Error> {
     let result = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
         let profile = get_profile(&target_username, &mut conn)?;
-        Ok(profile)
+
+        // blocked users cannot see the profile of the blocker
+        match blocked_between(profile.id, current_user.id, &mut conn) {
+            Ok(false) => Ok(profile),
+            Ok(true) => Err(ServiceError::NotFound(
+                "user_not_found",
+                message!("user-not-found", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
+        }
     })
     .await??;
 
@@ -105,66 +157,354 @@ async fn get_profile_posts(
     pool: web::Data<DbPool>,
     current_user: UserDetails,
 ) -> Result<HttpResponse, Error> {
+    use schema::bookmarks::dsl::{
+        bookmarks, deleted as bookmark_deleted, post_id as bookmark_post_id,
+        user_id as bookmark_user_id,
+    };
     use schema::likes::dsl::{
-        deleted as like_deleted, likes, post_id as like_post_id, user_id as like_user_id,
+        deleted as like_deleted, emoji as like_emoji, likes, post_id as like_post_id,
+        user_id as like_user_id,
     };
     use schema::posts::dsl::{
-        created_at as post_created_at, deleted as post_deleted, id as post_id, posts,
+        created_at as post_created_at, deleted as post_deleted, id as post_id,
+        pinned_at as post_pinned_at, posts, published as post_published,
     };
     use schema::users::dsl::{deleted as user_deleted, username, users};
 
-    let returned_posts = web::block(move || {
+    let (pinned_posts, returned_posts) = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
+        // pinned posts come with the first page and are left out of the chronological ones
+        let pinned_ids: Vec<i32> = if pagination.offset == 0 {
+            match posts
+                .inner_join(users)
+                .filter(
+                    post_pinned_at
+                        .is_not_null()
+                        .and(post_deleted.eq(false))
+                        .and(post_published.eq(true))
+                        .and(username.eq(target_username.as_str()))
+                        .and(user_deleted.eq(false)),
+                )
+                .select(post_id)
+                .order_by(post_pinned_at.desc())
+                .load(&mut conn)
+            {
+                Ok(pinned_ids) => pinned_ids,
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            Vec::new()
+        };
+
         match posts
             .inner_join(users)
             .left_join(
                 likes.on(like_post_id
                     .eq(post_id)
                     .and(like_user_id.eq(current_user.id))
+                    .and(like_emoji.eq(LIKE_REACTION))
                     .and(like_deleted.eq(false))),
             )
+            .left_join(
+                bookmarks.on(bookmark_post_id
+                    .eq(post_id)
+                    .and(bookmark_user_id.eq(current_user.id))
+                    .and(bookmark_deleted.eq(false))),
+            )
             .filter(
                 post_deleted
                     .eq(false)
+                    .and(post_published.eq(true))
+                    .and(post_pinned_at.is_null())
                     .and(username.eq(target_username.as_str()))
-                    .and(user_deleted.eq(false)),
+                    .and(user_deleted.eq(false))
+                    .and(visible_to(current_user.id)),
             )
             .select((
                 Post::as_select(),
                 Poster::as_select(),
                 Option::<Like>::as_select(),
+                Option::<Bookmark>::as_select(),
             ))
             .offset(pagination.offset as i64)
             .limit(pagination.limit as i64)
             .order_by(post_created_at.desc())
-            .load::<(Post, Poster, Option<Like>)>(&mut conn)
+            .load::<PostRow>(&mut conn)
+            .and_then(|chronological_posts| {
+                // visibility is checked when loading the pinned posts
+                let mut pinned = load_posts_by_id(&pinned_ids, current_user.id, &mut conn)?;
+                let pinned_posts: Vec<PostRow> = pinned_ids
+                    .iter()
+                    .filter_map(|id| pinned.remove(id))
+                    .collect();
+
+                let mut pinned_reads = load_post_reads(pinned_posts, current_user.id, &mut conn)?;
+                for read in pinned_reads.iter_mut() {
+                    read.mark_pinned();
+                }
+                let pinned_reads = apply_preferences(pinned_reads, current_user.id, &mut conn)?;
+
+                let reads = load_post_reads(chronological_posts, current_user.id, &mut conn)?;
+                let reads = apply_preferences(reads, current_user.id, &mut conn)?;
+                Ok((pinned_reads, reads))
+            }) {
+            Ok(result) => Ok(result),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(ProfileTimelineRead {
+        pinned: pinned_posts,
+        posts: returned_posts,
+    }))
+}
+
+#[get("/{target_username}/likes")]
+async fn get_profile_likes(
+    target_username: web::Path<String>,
+    pagination: web::Query<Pagination>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::likes::dsl::{
+        created_at as like_created_at, deleted as like_deleted, emoji as like_emoji, likes,
+        post_id as like_post_id, user_id as like_user_id,
+    };
+    use schema::users::dsl::{deleted as user_deleted, username, users};
+
+    let returned_posts = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let owner: LikesOwner = match users
+            .filter(
+                username
+                    .eq(target_username.as_str())
+                    .and(user_deleted.eq(false)),
+            )
+            .select(LikesOwner::as_select())
+            .first(&mut conn)
         {
+            Ok(owner) => owner,
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::NotFound(
+                    "user_not_found",
+                    message!("user-not-found", username = target_username),
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        // blocked users cannot see the profile of the blocker
+        match blocked_between(owner.id, current_user.id, &mut conn) {
+            Ok(false) => {}
+            Ok(true) => {
+                return Err(ServiceError::NotFound(
+                    "user_not_found",
+                    message!("user-not-found", username = target_username),
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        if !owner.likes_public && owner.id != current_user.id {
+            return Err(ServiceError::Forbidden(
+                "likes_private",
+                message!("likes-private", username = target_username),
+            ));
+        }
+
+        // most recently liked first, so keep the order of the likes
+        let liked_post_ids: Vec<i32> = match likes
+            .filter(
+                like_user_id
+                    .eq(owner.id)
+                    .and(like_emoji.eq(LIKE_REACTION))
+                    .and(like_deleted.eq(false)),
+            )
+            .select(like_post_id)
+            .offset(pagination.offset as i64)
+            .limit(pagination.limit as i64)
+            .order_by(like_created_at.desc())
+            .load(&mut conn)
+        {
+            Ok(liked_post_ids) => liked_post_ids,
+            Err(err) => return Err(err.into()),
+        };
+
+        match load_posts_by_id(&liked_post_ids, current_user.id, &mut conn).and_then(
+            |mut liked_posts| {
+                let rows: Vec<PostRow> = liked_post_ids
+                    .iter()
+                    .filter_map(|id| liked_posts.remove(id))
+                    .collect();
+                load_post_reads(rows, current_user.id, &mut conn)
+            },
+        ) {
             Ok(returned_posts) => Ok(returned_posts),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(ProfilePostsRead {
+        posts: returned_posts,
+    }))
+}
+
+#[post("/{target_username}/follow")]
+async fn follow_profile(
+    target_username: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::follows::dsl::follows;
+    use schema::users::dsl::{deleted as user_deleted, id as user_id, username, users};
+
+    let followed_username = target_username.to_string();
+    let follow = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível carregar as postagens feitas por {}.",
-                    target_username.as_str()
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Follow, diesel::result::Error, _>(|conn| {
+            let target_id: i32 = users
+                .filter(
+                    username
+                        .eq(target_username.as_str())
+                        .and(user_deleted.eq(false))
+                        .and(user_id.ne(current_user.id)),
+                )
+                .select(user_id)
+                .first(conn)?;
+
+            if blocked_between(current_user.id, target_id, conn)? {
+                return Err(diesel::result::Error::NotFound);
             }
+
+            diesel::insert_into(follows)
+                .values(&NewFollow {
+                    follower_id: current_user.id,
+                    followee_id: target_id,
+                })
+                .returning(Follow::as_returning())
+                .get_result(conn)
+        });
+
+        match result {
+            Ok(follow) => Ok(follow),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "user_not_found",
+                message!("user-not-found", username = target_username),
+            )),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(ServiceError::Conflict(
+                "already_following",
+                message!("already-following", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
         }
     })
     .await??;
 
-    let returned_posts: Vec<PostRead> = returned_posts
-        .into_iter()
-        .map(|(post, poster, like)| PostRead::from((post, poster, like)))
-        .collect();
+    Ok(HttpResponse::Ok().json(FollowRead {
+        username: followed_username,
+        created_at: follow.created_at.to_string(),
+    }))
+}
 
-    Ok(HttpResponse::Ok().json(ProfilePostsRead {
-        posts: returned_posts,
+#[delete("/{target_username}/follow")]
+async fn unfollow_profile(
+    target_username: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::follows::dsl::{
+        deleted as follow_deleted, followee_id, follower_id, follows, id as follow_id,
+    };
+    use schema::users::dsl::{deleted as user_deleted, id as user_id, username, users};
+
+    let followed_username = target_username.to_string();
+    let follow = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Follow, diesel::result::Error, _>(|conn| {
+            let target_id: i32 = users
+                .filter(
+                    username
+                        .eq(target_username.as_str())
+                        .and(user_deleted.eq(false)),
+                )
+                .select(user_id)
+                .first(conn)?;
+
+            // check if follow exists
+            let follow: Follow = follows
+                .filter(
+                    follower_id
+                        .eq(current_user.id)
+                        .and(followee_id.eq(target_id))
+                        .and(follow_deleted.eq(false)),
+                )
+                .select(Follow::as_select())
+                .first(conn)?;
+
+            // set as deleted
+            diesel::update(follows)
+                .filter(follow_id.eq(follow.id))
+                .set(follow_deleted.eq(true))
+                .execute(conn)?;
+
+            Ok(follow)
+        });
+
+        match result {
+            Ok(follow) => Ok(follow),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "follow_not_found",
+                message!("follow-not-found", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(FollowRead {
+        username: followed_username,
+        created_at: follow.created_at.to_string(),
     }))
 }
 
@@ -172,6 +512,9 @@ pub fn configure(cfg: &mut ServiceConfig) {
     cfg.service(
         web::scope("/profiles")
             .service(get_profile_details)
-            .service(get_profile_posts),
+            .service(get_profile_posts)
+            .service(get_profile_likes)
+            .service(follow_profile)
+            .service(unfollow_profile),
     );
 }
@@ -0,0 +1,503 @@
+use actix_web::{
+    delete, get, post,
+    web::{self, ServiceConfig},
+    Error, HttpResponse,
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, JoinOnDsl, QueryDsl,
+    Queryable, RunQueryDsl, Selectable, SelectableHelper,
+};
+use microblogs::{
+    errors::ServiceError, hub::Subscriber, message, schema, DbConn, DbPool, Pagination, WritePool,
+};
+use serde::Serialize;
+
+use crate::users::UserDetails;
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::blocks)]
+struct NewBlock {
+    pub blocker_id: i32,
+    pub blocked_id: i32,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::blocks)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct Block {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::mutes)]
+struct NewMute {
+    pub muter_id: i32,
+    pub muted_id: i32,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = schema::mutes)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+struct Mute {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct RelationRead {
+    username: String,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+struct RelationsRead {
+    users: Vec<RelationRead>,
+}
+
+// Whether either user blocked the other.
+pub fn blocked_between(
+    user_id: i32,
+    other_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<bool, diesel::result::Error> {
+    use schema::blocks::dsl::{blocked_id, blocker_id, blocks, deleted};
+
+    let found: Vec<Block> = blocks
+        .filter(
+            blocker_id
+                .eq(user_id)
+                .and(blocked_id.eq(other_user_id))
+                .or(blocker_id.eq(other_user_id).and(blocked_id.eq(user_id))),
+        )
+        .filter(deleted.eq(false))
+        .select(Block::as_select())
+        .load(conn)?;
+
+    Ok(!found.is_empty())
+}
+
+// The follows, blocks and mutes that decide which new posts reach a user's
+// event stream.
+pub fn load_subscriber(
+    user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Subscriber, diesel::result::Error> {
+    use schema::blocks::dsl::{blocked_id, blocker_id, blocks, deleted as block_deleted};
+    use schema::follows::dsl::{deleted as follow_deleted, followee_id, follower_id, follows};
+    use schema::mutes::dsl::{deleted as mute_deleted, muted_id, muter_id, mutes};
+
+    let followee_ids: Vec<i32> = follows
+        .filter(follower_id.eq(user_id))
+        .filter(follow_deleted.eq(false))
+        .select(followee_id)
+        .load(conn)?;
+    let blocked_ids: Vec<i32> = blocks
+        .filter(blocker_id.eq(user_id))
+        .filter(block_deleted.eq(false))
+        .select(blocked_id)
+        .load(conn)?;
+    let blocker_ids: Vec<i32> = blocks
+        .filter(blocked_id.eq(user_id))
+        .filter(block_deleted.eq(false))
+        .select(blocker_id)
+        .load(conn)?;
+    let muted_ids: Vec<i32> = mutes
+        .filter(muter_id.eq(user_id))
+        .filter(mute_deleted.eq(false))
+        .select(muted_id)
+        .load(conn)?;
+
+    Ok(Subscriber {
+        user_id,
+        followee_ids: followee_ids.into_iter().collect(),
+        hidden_ids: blocked_ids
+            .into_iter()
+            .chain(blocker_ids)
+            .chain(muted_ids)
+            .collect(),
+    })
+}
+
+fn find_other_user(
+    target_username: &str,
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<i32, diesel::result::Error> {
+    use schema::users::dsl::{deleted, id, username, users};
+
+    users
+        .filter(
+            username
+                .eq(target_username)
+                .and(deleted.eq(false))
+                .and(id.ne(current_user_id)),
+        )
+        .select(id)
+        .first(conn)
+}
+
+#[post("/{target_username}")]
+async fn block_user(
+    target_username: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::blocks::dsl::blocks;
+    use schema::follows::dsl::{deleted as follow_deleted, followee_id, follower_id, follows};
+
+    let blocked_username = target_username.to_string();
+    let block = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Block, diesel::result::Error, _>(|conn| {
+            let target_id = find_other_user(&target_username, current_user.id, conn)?;
+
+            // blocking also undoes follows in both directions
+            diesel::update(follows)
+                .filter(
+                    follower_id
+                        .eq(current_user.id)
+                        .and(followee_id.eq(target_id))
+                        .or(follower_id
+                            .eq(target_id)
+                            .and(followee_id.eq(current_user.id))),
+                )
+                .filter(follow_deleted.eq(false))
+                .set(follow_deleted.eq(true))
+                .execute(conn)?;
+
+            diesel::insert_into(blocks)
+                .values(&NewBlock {
+                    blocker_id: current_user.id,
+                    blocked_id: target_id,
+                })
+                .returning(Block::as_returning())
+                .get_result(conn)
+        });
+
+        match result {
+            Ok(block) => Ok(block),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "user_not_found",
+                message!("user-not-found", username = target_username),
+            )),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(ServiceError::Conflict(
+                "already_blocked",
+                message!("already-blocked", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RelationRead {
+        username: blocked_username,
+        created_at: block.created_at.to_string(),
+    }))
+}
+
+#[delete("/{target_username}")]
+async fn unblock_user(
+    target_username: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::blocks::dsl::{blocked_id, blocker_id, blocks, deleted, id as block_id};
+
+    let blocked_username = target_username.to_string();
+    let block = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Block, diesel::result::Error, _>(|conn| {
+            let target_id = find_other_user(&target_username, current_user.id, conn)?;
+
+            // check if block exists
+            let block: Block = blocks
+                .filter(
+                    blocker_id
+                        .eq(current_user.id)
+                        .and(blocked_id.eq(target_id))
+                        .and(deleted.eq(false)),
+                )
+                .select(Block::as_select())
+                .first(conn)?;
+
+            // set as deleted
+            diesel::update(blocks)
+                .filter(block_id.eq(block.id))
+                .set(deleted.eq(true))
+                .execute(conn)?;
+
+            Ok(block)
+        });
+
+        match result {
+            Ok(block) => Ok(block),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "block_not_found",
+                message!("block-not-found", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RelationRead {
+        username: blocked_username,
+        created_at: block.created_at.to_string(),
+    }))
+}
+
+#[get("/list")]
+async fn list_blocks(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::blocks::dsl::{blocked_id, blocker_id, blocks, created_at, deleted};
+    use schema::users::dsl::{deleted as user_deleted, id as user_id, username, users};
+
+    let blocked = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match blocks
+            .inner_join(users.on(user_id.eq(blocked_id)))
+            .filter(
+                blocker_id
+                    .eq(current_user.id)
+                    .and(deleted.eq(false))
+                    .and(user_deleted.eq(false)),
+            )
+            .select((username, created_at))
+            .offset(pagination.offset as i64)
+            .limit(pagination.limit as i64)
+            .order_by(created_at.desc())
+            .load::<(String, NaiveDateTime)>(&mut conn)
+        {
+            Ok(blocked) => Ok(blocked),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RelationsRead {
+        users: blocked
+            .into_iter()
+            .map(|(blocked_username, blocked_at)| RelationRead {
+                username: blocked_username,
+                created_at: blocked_at.to_string(),
+            })
+            .collect(),
+    }))
+}
+
+#[post("/{target_username}")]
+async fn mute_user(
+    target_username: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::mutes::dsl::mutes;
+
+    let muted_username = target_username.to_string();
+    let mute = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Mute, diesel::result::Error, _>(|conn| {
+            let target_id = find_other_user(&target_username, current_user.id, conn)?;
+
+            diesel::insert_into(mutes)
+                .values(&NewMute {
+                    muter_id: current_user.id,
+                    muted_id: target_id,
+                })
+                .returning(Mute::as_returning())
+                .get_result(conn)
+        });
+
+        match result {
+            Ok(mute) => Ok(mute),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "user_not_found",
+                message!("user-not-found", username = target_username),
+            )),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(ServiceError::Conflict(
+                "already_muted",
+                message!("already-muted", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RelationRead {
+        username: muted_username,
+        created_at: mute.created_at.to_string(),
+    }))
+}
+
+#[delete("/{target_username}")]
+async fn unmute_user(
+    target_username: web::Path<String>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::mutes::dsl::{deleted, id as mute_id, muted_id, muter_id, mutes};
+
+    let muted_username = target_username.to_string();
+    let mute = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        let result = conn.transaction::<Mute, diesel::result::Error, _>(|conn| {
+            let target_id = find_other_user(&target_username, current_user.id, conn)?;
+
+            // check if mute exists
+            let mute: Mute = mutes
+                .filter(
+                    muter_id
+                        .eq(current_user.id)
+                        .and(muted_id.eq(target_id))
+                        .and(deleted.eq(false)),
+                )
+                .select(Mute::as_select())
+                .first(conn)?;
+
+            // set as deleted
+            diesel::update(mutes)
+                .filter(mute_id.eq(mute.id))
+                .set(deleted.eq(true))
+                .execute(conn)?;
+
+            Ok(mute)
+        });
+
+        match result {
+            Ok(mute) => Ok(mute),
+            Err(diesel::result::Error::NotFound) => Err(ServiceError::NotFound(
+                "mute_not_found",
+                message!("mute-not-found", username = target_username),
+            )),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RelationRead {
+        username: muted_username,
+        created_at: mute.created_at.to_string(),
+    }))
+}
+
+#[get("/list")]
+async fn list_mutes(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, Error> {
+    use schema::mutes::dsl::{created_at, deleted, muted_id, muter_id, mutes};
+    use schema::users::dsl::{deleted as user_deleted, id as user_id, username, users};
+
+    let muted = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match mutes
+            .inner_join(users.on(user_id.eq(muted_id)))
+            .filter(
+                muter_id
+                    .eq(current_user.id)
+                    .and(deleted.eq(false))
+                    .and(user_deleted.eq(false)),
+            )
+            .select((username, created_at))
+            .offset(pagination.offset as i64)
+            .limit(pagination.limit as i64)
+            .order_by(created_at.desc())
+            .load::<(String, NaiveDateTime)>(&mut conn)
+        {
+            Ok(muted) => Ok(muted),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(RelationsRead {
+        users: muted
+            .into_iter()
+            .map(|(muted_username, muted_at)| RelationRead {
+                username: muted_username,
+                created_at: muted_at.to_string(),
+            })
+            .collect(),
+    }))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/blocks")
+            .service(list_blocks)
+            .service(block_user)
+            .service(unblock_user),
+    )
+    .service(
+        web::scope("/mutes")
+            .service(list_mutes)
+            .service(mute_user)
+            .service(unmute_user),
+    );
+}
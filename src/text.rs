@@ -0,0 +1,42 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+// Normalizes user provided text to NFC, dropping control characters other than
+// line breaks and tabs, and trims surrounding whitespace.
+pub fn normalize(text: &str) -> String {
+    text.nfc()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Counts user-perceived characters, so an emoji with modifiers counts as one.
+pub fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+// Usernames are what mentions link to, so they use the same characters a
+// mention can contain and fit the column they're stored in.
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+pub fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.chars().count() <= MAX_USERNAME_LENGTH
+        && username.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// Only catches obvious mistakes, as the address is never mailed to.
+pub fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            email.len() <= 254
+                && !email.contains(char::is_whitespace)
+                && !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && domain.split('.').all(|label| !label.is_empty())
+        }
+        None => false,
+    }
+}
@@ -1,22 +1,46 @@
 use std::future::{ready, Ready};
 
 use actix_web::{
-    get, post,
+    get, post, put,
     web::{self, ServiceConfig},
     Error, FromRequest, HttpRequest, HttpResponse,
 };
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2, PasswordHash, PasswordVerifier,
-};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use diesel::{
-    query_dsl::filter_dsl::FilterDsl, query_dsl::methods::SelectDsl, BoolExpressionMethods,
-    ExpressionMethods, Insertable, Queryable, RunQueryDsl, Selectable, SelectableHelper,
+    query_dsl::filter_dsl::FilterDsl, query_dsl::methods::SelectDsl, AsChangeset,
+    BoolExpressionMethods, ExpressionMethods, Insertable, Queryable, RunQueryDsl, Selectable,
+    SelectableHelper,
 };
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
-use microblogs::{errors::ServiceError, schema, AppState, DbPool};
+use microblogs::{
+    errors::{FieldError, ServiceError},
+    hash_password,
+    i18n::{set_locale, supported_locale, SUPPORTED_LOCALES},
+    message, schema,
+    text::{is_valid_email, is_valid_username, MAX_USERNAME_LENGTH},
+    AppState, DbConn, DbPool, WritePool,
+};
+
+// How posts flagged with sensitive media are shown in feeds.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SensitiveMedia {
+    Blur,
+    Show,
+    Hide,
+}
+
+impl SensitiveMedia {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensitiveMedia::Blur => "blur",
+            SensitiveMedia::Show => "show",
+            SensitiveMedia::Hide => "hide",
+        }
+    }
+}
 
 #[derive(Deserialize)]
 struct UserRegister {
@@ -34,6 +58,21 @@ struct UserLogin {
     password: String,
 }
 
+#[derive(Deserialize)]
+struct PreferencesUpdate {
+    likes_public: Option<bool>,
+    sensitive_media: Option<SensitiveMedia>,
+    locale: Option<String>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = schema::users)]
+struct PreferencesChangeset {
+    likes_public: Option<bool>,
+    sensitive_media: Option<&'static str>,
+    locale: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct Claims {
     sub: String,
@@ -66,18 +105,20 @@ impl FromRequest for UserDetails {
         let pool = match req.app_data::<web::Data<DbPool>>() {
             Some(pool) => pool,
             None => {
-                return ready(Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                ))
+                return ready(Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                )
                 .into()))
             }
         };
         let app_state = match req.app_data::<web::Data<AppState>>() {
             Some(app_state) => app_state,
             None => {
-                return ready(Err(ServiceError::InternalServerError(format!(
-                    "Impossível obter dados da aplicação."
-                ))
+                return ready(Err(ServiceError::InternalServerError(
+                    "internal_error",
+                    message!("app-state-unavailable"),
+                )
                 .into()))
             }
         };
@@ -88,9 +129,10 @@ impl FromRequest for UserDetails {
                 let header_str = match header.to_str() {
                     Ok(header) => header,
                     Err(_) => {
-                        return ready(Err(ServiceError::InternalServerError(format!(
-                            "Falha ao converter o cabeçalho de autorização."
-                        ))
+                        return ready(Err(ServiceError::Unauthorized(
+                            "invalid_authorization_header",
+                            message!("authorization-header-not-converted"),
+                        )
                         .into()))
                     }
                 };
@@ -98,9 +140,10 @@ impl FromRequest for UserDetails {
                 let bearer_token = match header_str.strip_prefix("Bearer ") {
                     Some(token) => token.to_string(),
                     None => {
-                        return ready(Err(ServiceError::InternalServerError(format!(
-                            "Falha ao obter a chave de autorização do valor do cabeçalho."
-                        ))
+                        return ready(Err(ServiceError::Unauthorized(
+                            "invalid_authorization_header",
+                            message!("authorization-token-missing"),
+                        )
                         .into()))
                     }
                 };
@@ -114,9 +157,10 @@ impl FromRequest for UserDetails {
                 let access_token = match access_token_cookie {
                     Some(cookie) => cookie.value().to_owned(),
                     None => {
-                        return ready(Err(ServiceError::Unauthorized(format!(
-                            "Acesso negado porque o cookie \"accessToken\" não foi encontrado."
-                        ))
+                        return ready(Err(ServiceError::Unauthorized(
+                            "missing_access_token",
+                            message!("missing-access-token"),
+                        )
                         .into()))
                     }
                 };
@@ -133,9 +177,10 @@ impl FromRequest for UserDetails {
         ) {
             Ok(token) => token,
             Err(_) => {
-                return ready(Err(ServiceError::Unauthorized(format!(
-                    "Não foi possível decodificar a chave."
-                ))
+                return ready(Err(ServiceError::Unauthorized(
+                    "invalid_access_token",
+                    message!("invalid-access-token"),
+                )
                 .into()))
             }
         };
@@ -144,9 +189,10 @@ impl FromRequest for UserDetails {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return ready(Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível conectar ao banco de dados."
-                ))
+                return ready(Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                )
                 .into()))
             }
         };
@@ -157,15 +203,21 @@ impl FromRequest for UserDetails {
             .first(&mut conn)
         {
             Ok(user) => user,
-            Err(_) => {
-                return ready(Err(ServiceError::Unauthorized(format!(
-                    "Usuário \"{}\" inexistente.",
-                    username_in_session
-                ))
+            Err(diesel::result::Error::NotFound) => {
+                return ready(Err(ServiceError::Unauthorized(
+                    "invalid_access_token",
+                    message!("unknown-user", username = username_in_session),
+                )
                 .into()))
             }
+            Err(err) => return ready(Err(ServiceError::from(err).into())),
         };
 
+        // the locale picked by the user wins over the one of the browser
+        if let Some(user_locale) = &user.locale {
+            set_locale(user_locale);
+        }
+
         ready(Ok(user.into()))
     }
 }
@@ -179,12 +231,34 @@ struct AccessInfo {
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = schema::users)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(microblogs::DbBackend))]
 struct User {
     pub id: i32,
     pub username: String,
     pub real_name: String,
     pub password: String,
+    pub locale: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = schema::users)]
+#[diesel(check_for_backend(microblogs::DbBackend))]
+pub struct Preferences {
+    pub likes_public: bool,
+    pub sensitive_media: String,
+    pub locale: Option<String>,
+}
+
+pub fn load_preferences(
+    current_user_id: i32,
+    conn: &mut DbConn,
+) -> Result<Preferences, diesel::result::Error> {
+    use schema::users::dsl::*;
+
+    users
+        .filter(id.eq(current_user_id))
+        .select(Preferences::as_select())
+        .first(conn)
 }
 
 #[derive(Insertable)]
@@ -200,35 +274,61 @@ struct NewUser<'a> {
 #[post("/register")]
 async fn register_user(
     info: web::Json<UserRegister>,
-    pool: web::Data<DbPool>,
+    pool: web::Data<WritePool>,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, actix_web::Error> {
     use schema::users::dsl::*;
 
+    if !app_state.registrations_open {
+        return Err(ServiceError::Forbidden(
+            "registrations_closed",
+            message!("registrations-closed"),
+        )
+        .into());
+    }
+
+    let mut errors = Vec::new();
+    if !is_valid_username(&info.username) {
+        errors.push(FieldError::new(
+            "username",
+            "invalid",
+            message!("invalid-username", max = MAX_USERNAME_LENGTH),
+        ));
+    }
+    if !is_valid_email(&info.email) {
+        errors.push(FieldError::new(
+            "email",
+            "invalid",
+            message!("invalid-email"),
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(ServiceError::InvalidFields(errors).into());
+    }
+
     let user = web::block(move || {
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Não foi possível conectar ao banco de dados."
-                )));
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ));
             }
         };
 
-        let password_bytes = info.password.as_bytes();
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let hashed_password = match argon2.hash_password(password_bytes, &salt) {
-            Ok(hashed_password) => hashed_password.to_string(),
+        let hashed_password = match hash_password(&info.password) {
+            Ok(hashed_password) => hashed_password,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Falha ao criptografar a senha."
-                )));
+                return Err(ServiceError::InternalServerError(
+                    "internal_error",
+                    message!("password-not-hashed"),
+                ));
             }
         };
         let new_user = NewUser {
             username: &info.username,
-            email: &info.email, // TODO: validate email
+            email: &info.email,
             real_name: &info.real_name,
             summary: &info.summary,
             password: &hashed_password,
@@ -239,19 +339,22 @@ async fn register_user(
             .returning(User::as_returning())
             .get_result(&mut conn)
         {
-            Ok(user) => return Ok(user),
-            Err(_) => {
-                return Err(ServiceError::BadRequest(format!(
-                    "Falha ao registrar o novo usuário."
-                )))
-            }
+            Ok(user) => Ok(user),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err(ServiceError::Conflict(
+                "user_already_exists",
+                message!("user-already-exists"),
+            )),
+            Err(err) => Err(ServiceError::from(err)),
         }
     })
     .await??;
 
     let claims = Claims {
         sub: user.username.clone(),
-        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+        exp: (chrono::Utc::now() + app_state.token_lifetime).timestamp() as usize,
     };
     let secret = app_state.secret_key.clone();
     let token = match encode(
@@ -261,9 +364,11 @@ async fn register_user(
     ) {
         Ok(token) => token,
         Err(_) => {
-            return Err(
-                ServiceError::InternalServerError(format!("Falha ao gerar a chave.")).into(),
+            return Err(ServiceError::InternalServerError(
+                "internal_error",
+                message!("token-not-generated"),
             )
+            .into())
         }
     };
 
@@ -289,25 +394,28 @@ async fn authenticate_user(
         let mut conn = match pool.get() {
             Ok(conn) => conn,
             Err(_) => {
-                return Err(ServiceError::InternalServerError(format!(
-                    "Impossível conectar ao banco de dados."
-                )))
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
             }
         };
 
+        // unknown users get the same answer as wrong passwords, so logins
+        // don't reveal which usernames exist
         let user: User = match users
-            .filter(username.eq(target_username.as_str()))
+            .filter(username.eq(target_username.as_str()).and(deleted.eq(false)))
             .select(User::as_select())
             .first::<User>(&mut conn)
         {
             Ok(user) => user,
-            Err(_) => {
-                return Err(ServiceError::Unauthorized(format!(
-                    "Usuário \"{}\" inexistente.",
-                    target_username
+            Err(diesel::result::Error::NotFound) => {
+                return Err(ServiceError::Unauthorized(
+                    "invalid_credentials",
+                    message!("invalid-credentials"),
                 ))
-                .into())
             }
+            Err(err) => return Err(err.into()),
         };
 
         Ok(user)
@@ -317,9 +425,10 @@ async fn authenticate_user(
     let parsed_password_hash = match PasswordHash::new(&user.password) {
         Ok(hash) => hash,
         Err(_) => {
-            return Err(ServiceError::InternalServerError(format!(
-                "Falha ao descriptografar a senha."
-            ))
+            return Err(ServiceError::InternalServerError(
+                "internal_error",
+                message!("password-not-verified"),
+            )
             .into())
         }
     };
@@ -328,12 +437,16 @@ async fn authenticate_user(
     let verified = argon2.verify_password(target_password.as_bytes(), &parsed_password_hash);
 
     if verified.is_err() {
-        return Err(ServiceError::Unauthorized(format!("Credenciais inválidas.")).into());
+        return Err(ServiceError::Unauthorized(
+            "invalid_credentials",
+            message!("invalid-credentials"),
+        )
+        .into());
     }
 
     let claims = Claims {
         sub: user.username.clone(),
-        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+        exp: (chrono::Utc::now() + app_state.token_lifetime).timestamp() as usize,
     };
     let secret = app_state.secret_key.clone();
     let token = match encode(
@@ -343,9 +456,10 @@ async fn authenticate_user(
     ) {
         Ok(token) => token,
         Err(_) => {
-            return Err(ServiceError::InternalServerError(format!(
-                "Falha ao gerar uma nova chave."
-            ))
+            return Err(ServiceError::InternalServerError(
+                "internal_error",
+                message!("token-not-renewed"),
+            )
             .into())
         }
     };
@@ -365,7 +479,7 @@ async fn refresh_access(
 ) -> Result<HttpResponse, actix_web::Error> {
     let claims = Claims {
         sub: current_user.username.clone(),
-        exp: (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+        exp: (chrono::Utc::now() + app_state.token_lifetime).timestamp() as usize,
     };
     let secret = app_state.secret_key.clone();
     let token = match encode(
@@ -375,9 +489,10 @@ async fn refresh_access(
     ) {
         Ok(token) => token,
         Err(_) => {
-            return Err(ServiceError::InternalServerError(format!(
-                "Falha ao gerar uma nova chave."
-            ))
+            return Err(ServiceError::InternalServerError(
+                "internal_error",
+                message!("token-not-renewed"),
+            )
             .into())
         }
     };
@@ -390,11 +505,106 @@ async fn refresh_access(
     Ok(HttpResponse::Ok().json(access_info))
 }
 
+#[get("/preferences")]
+async fn get_preferences(
+    pool: web::Data<DbPool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        match load_preferences(current_user.id, &mut conn) {
+            Ok(preferences) => Ok(preferences),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+#[put("/preferences")]
+async fn update_preferences(
+    info: web::Json<PreferencesUpdate>,
+    pool: web::Data<WritePool>,
+    current_user: UserDetails,
+) -> Result<HttpResponse, actix_web::Error> {
+    use schema::users::dsl::*;
+
+    let info = info.into_inner();
+    let mut changes = PreferencesChangeset {
+        likes_public: info.likes_public,
+        sensitive_media: info.sensitive_media.map(|mode| mode.as_str()),
+        locale: None,
+    };
+
+    if let Some(requested_locale) = &info.locale {
+        match supported_locale(requested_locale) {
+            Some(supported) => changes.locale = Some(supported.to_string()),
+            None => {
+                return Err(ServiceError::BadRequest(
+                    "unsupported_locale",
+                    message!(
+                        "unsupported-locale",
+                        locale = requested_locale,
+                        supported = SUPPORTED_LOCALES.join(", ")
+                    ),
+                )
+                .into())
+            }
+        }
+    }
+
+    let preferences = web::block(move || {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(_) => {
+                return Err(ServiceError::InternalServerError(
+                    "database_unavailable",
+                    message!("database-unavailable"),
+                ))
+            }
+        };
+
+        // an update without any field set is a no-op
+        let result = match (
+            changes.likes_public,
+            changes.sensitive_media,
+            &changes.locale,
+        ) {
+            (None, None, None) => load_preferences(current_user.id, &mut conn),
+            _ => diesel::update(users)
+                .filter(id.eq(current_user.id))
+                .set(&changes)
+                .returning(Preferences::as_returning())
+                .get_result(&mut conn),
+        };
+
+        match result {
+            Ok(preferences) => Ok(preferences),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
 pub fn configure(cfg: &mut ServiceConfig) {
     cfg.service(
         web::scope("/users")
             .service(register_user)
             .service(authenticate_user)
-            .service(refresh_access),
+            .service(refresh_access)
+            .service(get_preferences)
+            .service(update_preferences),
     );
 }
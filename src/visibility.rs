@@ -0,0 +1,118 @@
+use diesel::{dsl::sql, expression::SqlLiteral, sql_types::Bool};
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    Unlisted,
+    Followers,
+    Direct,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Followers => "followers",
+            Visibility::Direct => "direct",
+        }
+    }
+}
+
+// Posts the user is allowed to see: their own, public and unlisted ones,
+// followers-only posts of people they follow and direct posts mentioning them,
+// as long as neither side blocked the other.
+pub fn visible_to(user_id: i32) -> SqlLiteral<Bool> {
+    sql(&visible_to_sql(user_id))
+}
+
+// `visible_to` as raw SQL, for queries written with `sql_query`.
+pub fn visible_to_sql(user_id: i32) -> String {
+    condition(user_id, "'public', 'unlisted'", false)
+}
+
+// Same as `visible_to`, but leaves unlisted posts and muted users of others out
+// of timelines.
+pub fn listed_for(user_id: i32) -> SqlLiteral<Bool> {
+    sql(&condition(user_id, "'public'", true))
+}
+
+fn condition(user_id: i32, open_levels: &str, hide_muted: bool) -> String {
+    // `user_id` is an integer, so formatting it into the query is safe
+    let muted = if hide_muted {
+        format!(
+            "AND posts.poster_id NOT IN \
+             (SELECT muted_id FROM mutes WHERE muter_id = {user_id} AND deleted = FALSE)"
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        "(posts.poster_id = {user_id} \
+         OR ((posts.visibility IN ({open_levels}) \
+         OR (posts.visibility = 'followers' AND posts.poster_id IN \
+         (SELECT followee_id FROM follows WHERE follower_id = {user_id} AND deleted = FALSE)) \
+         OR (posts.visibility = 'direct' AND posts.id IN \
+         (SELECT post_id FROM mentions WHERE user_id = {user_id}))) \
+         AND posts.poster_id NOT IN \
+         (SELECT blocker_id FROM blocks WHERE blocked_id = {user_id} AND deleted = FALSE) \
+         AND posts.poster_id NOT IN \
+         (SELECT blocked_id FROM blocks WHERE blocker_id = {user_id} AND deleted = FALSE) \
+         {muted}))"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{connection::SimpleConnection, QueryDsl, RunQueryDsl};
+    use microblogs::{schema, test_connection};
+
+    use super::*;
+
+    #[test]
+    fn filters_posts_by_audience() {
+        use schema::posts::dsl::{id, posts};
+
+        let mut conn = test_connection();
+        conn.batch_execute(
+            "INSERT INTO users (id, username, email, real_name, summary, password) VALUES
+                 (1, 'a', 'a@example.com', 'A', '', 'x'),
+                 (2, 'b', 'b@example.com', 'B', '', 'x'),
+                 (3, 'c', 'c@example.com', 'C', '', 'x'),
+                 (4, 'd', 'd@example.com', 'D', '', 'x');
+             INSERT INTO posts (id, uuid, poster_id, body, visibility) VALUES
+                 (1, 'p1', 2, 'public', 'public'),
+                 (2, 'p2', 2, 'unlisted', 'unlisted'),
+                 (3, 'p3', 2, 'followers', 'followers'),
+                 (4, 'p4', 2, 'direct to a', 'direct'),
+                 (5, 'p5', 2, 'direct to c', 'direct'),
+                 (6, 'p6', 3, 'blocked', 'public'),
+                 (7, 'p7', 4, 'muted', 'public'),
+                 (8, 'p8', 4, 'followers', 'followers'),
+                 (9, 'p9', 1, 'own', 'direct');
+             INSERT INTO follows (follower_id, followee_id) VALUES (1, 2);
+             INSERT INTO mentions (post_id, user_id) VALUES (4, 1), (5, 3);
+             INSERT INTO blocks (blocker_id, blocked_id) VALUES (3, 1);
+             INSERT INTO mutes (muter_id, muted_id) VALUES (1, 4);",
+        )
+        .unwrap();
+
+        let visible: Vec<i32> = posts
+            .filter(visible_to(1))
+            .select(id)
+            .order_by(id)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(visible, vec![1, 2, 3, 4, 7, 9]);
+
+        let listed: Vec<i32> = posts
+            .filter(listed_for(1))
+            .select(id)
+            .order_by(id)
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(listed, vec![1, 3, 4, 9]);
+    }
+}